@@ -0,0 +1,121 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+//! Synthetic directory trees for benchmarking.
+
+#![allow(dead_code)]
+
+use fuzzy_tree::path::{build_paths, ArcPath};
+
+pub const SIZES: [usize; 3] = [10_000, 100_000, 1_000_000];
+
+const MONOREPO_DIRS: [&str; 8] = [
+	"src",
+	"lib",
+	"test",
+	"components",
+	"utils",
+	"api",
+	"internal",
+	"docs",
+];
+const MONOREPO_FILES: [&str; 8] = [
+	"main.rs",
+	"mod.rs",
+	"index.ts",
+	"README.md",
+	"util.py",
+	"Makefile",
+	"handler.go",
+	"config.yaml",
+];
+
+/// The shape of a synthetic directory tree.
+#[derive(Clone, Copy, Debug)]
+pub enum Shape {
+	/// Narrow directories nested up to 40 levels deep
+	Deep,
+	/// Two levels of very large directories
+	Wide,
+	/// A moderately deep and wide tree of realistic file names
+	Monorepo,
+}
+
+pub const SHAPES: [Shape; 3] = [Shape::Deep, Shape::Wide, Shape::Monorepo];
+
+impl Shape {
+	/// Max depth, directories per directory and files per directory.
+	fn params(self) -> (usize, usize, usize) {
+		match self {
+			Shape::Deep => (40, 2, 2),
+			Shape::Wide => (2, 1000, 1000),
+			Shape::Monorepo => (7, 6, 10),
+		}
+	}
+
+	fn dir_name(self, i: usize) -> String {
+		match self {
+			Shape::Monorepo => format!("{}{}", MONOREPO_DIRS[i % 8], i / 8),
+			_ => format!("d{:04}", i),
+		}
+	}
+
+	fn file_name(self, i: usize) -> String {
+		match self {
+			Shape::Monorepo => format!("{}{}", i / 8, MONOREPO_FILES[i % 8]),
+			_ => format!("f{:04}.txt", i),
+		}
+	}
+}
+
+/// Generate `n` path names (including the root `.`) of the given shape, along
+/// with whether each is a directory. Names are sorted as `create_paths` would
+/// sort them.
+pub fn names(shape: Shape, n: usize) -> Vec<(String, bool)> {
+	let mut names = vec![(".".to_string(), true)];
+	let mut stack = vec![(".".to_string(), 1)];
+	let (max_depth, n_dirs, n_files) = shape.params();
+
+	while let Some((dir, depth)) = stack.pop() {
+		for i in 0..n_files {
+			if names.len() >= n {
+				break;
+			}
+			names.push((format!("{}/{}", dir, shape.file_name(i)), false));
+		}
+		if depth < max_depth {
+			for i in (0..n_dirs).rev() {
+				if names.len() >= n {
+					break;
+				}
+				let name = format!("{}/{}", dir, shape.dir_name(i));
+				names.push((name.clone(), true));
+				stack.push((name, depth + 1));
+			}
+		}
+		if names.len() >= n {
+			break;
+		}
+	}
+
+	names.sort();
+	names
+}
+
+/// Generate `n` linked paths of the given shape.
+pub fn paths(shape: Shape, n: usize) -> Vec<ArcPath> {
+	build_paths(names(shape, n))
+}
+
+/// Generate `n` path names of the given shape as the output of a `find`-like
+/// command.
+pub fn stdout(shape: Shape, n: usize) -> Vec<u8> {
+	let mut stdout = Vec::new();
+	for (name, _) in names(shape, n) {
+		stdout.extend(name.into_bytes());
+		stdout.push(b'\n');
+	}
+	stdout
+}
@@ -0,0 +1,30 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+mod common;
+
+use common::Shape;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use fuzzy_tree::tree::Tree;
+
+/// A query matching every file means every ancestor is marked as matched. This
+/// should scale linearly with the number of paths.
+fn broad_query(c: &mut Criterion) {
+	let mut group = c.benchmark_group("match_paths/broad_query");
+	group.sample_size(10);
+
+	for &n in common::SIZES.iter() {
+		let mut tree = Tree::from_paths(common::paths(Shape::Wide, n));
+		tree.threads = 1;
+		group.throughput(Throughput::Elements(n as u64));
+		group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, _| {
+			b.iter(|| tree.filter("f"))
+		});
+	}
+	group.finish();
+}
+
+criterion_group!(benches, broad_query);
+criterion_main!(benches);
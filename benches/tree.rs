@@ -0,0 +1,124 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+//! Benchmarks for each stage of building and displaying a tree: ingesting
+//! command output, linking paths, filtering and rendering.
+
+mod common;
+
+use common::Shape;
+use criterion::measurement::WallTime;
+use criterion::{
+	criterion_group, criterion_main, BatchSize, BenchmarkGroup, BenchmarkId, Criterion, Throughput,
+};
+use fuzzy_tree::columns::Columns;
+use fuzzy_tree::path::build_paths;
+use fuzzy_tree::style::Style;
+use fuzzy_tree::tree::{tree_string, Tree};
+
+/// Typical queries for a shape: broad, multi-pattern, selective and unmatched.
+fn queries(shape: Shape) -> [&'static str; 4] {
+	match shape {
+		Shape::Monorepo => ["s", "src mod", "handler.go", "zzz"],
+		_ => ["f", "d0001 txt", "f0042.txt", "zzz"],
+	}
+}
+
+fn group<'a>(c: &'a mut Criterion, name: &str) -> BenchmarkGroup<'a, WallTime> {
+	let mut group = c.benchmark_group(name);
+	group.sample_size(10);
+	group
+}
+
+fn id(shape: Shape, n: usize) -> BenchmarkId {
+	BenchmarkId::new(format!("{:?}", shape), n)
+}
+
+/// Parsing and sorting command output. NB none of the generated paths exist,
+/// so checking whether each is a directory fails quickly.
+fn ingest(c: &mut Criterion) {
+	let mut group = group(c, "ingest");
+	for &shape in common::SHAPES.iter() {
+		for &n in common::SIZES.iter() {
+			let stdout = common::stdout(shape, n);
+			group.throughput(Throughput::Elements(n as u64));
+			group.bench_function(id(shape, n), |b| {
+				b.iter_batched(
+					|| stdout.clone(),
+					|stdout| Tree::from_stdout(stdout, false).unwrap(),
+					BatchSize::LargeInput,
+				)
+			});
+		}
+	}
+	group.finish();
+}
+
+/// Sorting path names and linking them into a tree.
+fn link(c: &mut Criterion) {
+	let mut group = group(c, "link");
+	for &shape in common::SHAPES.iter() {
+		for &n in common::SIZES.iter() {
+			let names = common::names(shape, n);
+			group.throughput(Throughput::Elements(n as u64));
+			group.bench_function(id(shape, n), |b| {
+				b.iter_batched(
+					|| names.clone(),
+					|names| Tree::from_paths(build_paths(names)),
+					BatchSize::LargeInput,
+				)
+			});
+		}
+	}
+	group.finish();
+}
+
+fn filter(c: &mut Criterion) {
+	let mut group = group(c, "filter");
+	for &shape in common::SHAPES.iter() {
+		for &n in common::SIZES.iter() {
+			let mut tree = Tree::from_paths(common::paths(shape, n));
+			group.throughput(Throughput::Elements(n as u64));
+			for query in queries(shape).iter() {
+				group.bench_function(BenchmarkId::new(format!("{:?}/{}", shape, query), n), |b| {
+					b.iter(|| tree.filter(query))
+				});
+			}
+		}
+	}
+	group.finish();
+}
+
+/// Rendering a fully open tree, and the same tree after filtering.
+fn render(c: &mut Criterion) {
+	let mut group = group(c, "render");
+	for &shape in common::SHAPES.iter() {
+		for &n in common::SIZES.iter() {
+			let mut tree = Tree::from_paths(common::paths(shape, n));
+			group.throughput(Throughput::Elements(n as u64));
+			group.bench_function(id(shape, n), |b| {
+				b.iter(|| {
+					tree_string(
+						&tree.tree,
+						tree.n_matches,
+						false,
+						&Columns::default(),
+						&Style::default(),
+					)
+				})
+			});
+
+			let query = queries(shape)[1];
+			tree.filter(query);
+			group.bench_function(BenchmarkId::new(format!("{:?}/{}", shape, query), n), |b| {
+				b.iter(|| tree.as_lines())
+			});
+		}
+	}
+	group.finish();
+}
+
+criterion_group!(benches, ingest, link, filter, render);
+criterion_main!(benches);
@@ -3,14 +3,43 @@
 // All files in the project carrying such notice may not be copied, modified, or
 // distributed except according to those terms.
 
+use crate::bind::Bindings;
+use crate::columns::Columns;
+use crate::config::{self, Config};
+use crate::export::Format;
+use crate::sort::SortBy;
+use crate::style::{self, Guides, TreeStyle};
+use crate::theme::Theme;
+use crate::tui::{Height, Layout};
 use crate::utils;
 use clap::{crate_version, App, Arg};
+use std::thread;
 
 #[derive(Debug)]
 pub struct Args {
 	pub cmd: String,
 	pub n_collapse: usize,
-	pub n_lines: usize,
+	pub layout: Layout,
+	pub threads: usize,
+	pub compact_dirs: bool,
+	pub sort_by: SortBy,
+	pub reverse: bool,
+	pub columns: Columns,
+	pub du: bool,
+	pub follow: bool,
+	pub icons: bool,
+	pub theme: Theme,
+	pub guides: Guides,
+	pub filter: Option<String>,
+	pub flat: bool,
+	pub print_tree: Option<String>,
+	pub max_depth: Option<usize>,
+	pub export: Option<Format>,
+	/// Command to preview paths with, empty for the built-in preview
+	pub preview: Option<String>,
+	/// Whether to handle clicks and the mouse wheel
+	pub mouse: bool,
+	pub bindings: Bindings,
 }
 
 #[cfg_attr(tarpaulin, skip)]
@@ -36,30 +65,377 @@ pub fn collect() -> Args {
 				.takes_value(true),
 		)
 		.arg(
-			Arg::with_name("n_lines")
+			Arg::with_name("height")
 				.short("l")
-				.long("n-lines")
+				.long("height")
+				.alias("n-lines")
+				.value_name("HEIGHT")
+				.help("Max number of lines to use, or a percentage of the terminal, e.g. 40%")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("fullscreen")
+				.long("fullscreen")
+				.conflicts_with("height")
+				.help("Use the whole terminal, restoring its contents on exit"),
+		)
+		.arg(
+			Arg::with_name("layout")
+				.long("layout")
+				.value_name("LAYOUT")
+				.help("Draw the tree from the top down (default) or the bottom up (reverse)")
+				.possible_values(&["default", "reverse"])
+				.default_value("default")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("prompt_position")
+				.long("prompt-position")
+				.value_name("POSITION")
+				.help("Draw the prompt and info line above or below the tree")
+				.possible_values(&["top", "bottom"])
+				.default_value("top")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("bind")
+				.long("bind")
+				.value_name("KEY:ACTION,...")
+				.help("Bind keys to actions, e.g. ctrl-n:down,ctrl-k:up")
+				.takes_value(true)
+				.multiple(true)
+				.number_of_values(1),
+		)
+		.arg(
+			Arg::with_name("no_mouse")
+				.long("no-mouse")
+				.help("Leave the mouse to the terminal, e.g. for selecting text"),
+		)
+		.arg(
+			Arg::with_name("threads")
+				.short("j")
+				.long("threads")
+				.value_name("N")
+				.help("Number of threads to use for matching (defaults to the number of CPUs)")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("compact_dirs")
+				.long("compact-dirs")
+				.help("Show chains of directories which contain a single directory on one line"),
+		)
+		.arg(
+			Arg::with_name("sort")
+				.short("s")
+				.long("sort")
+				.value_name("ORDER")
+				.help("Order in which to show the paths in each directory (cycle with ctrl-s)")
+				.possible_values(&[
+					"name",
+					"natural",
+					"dirs-first",
+					"mtime",
+					"size",
+					"extension",
+				])
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("reverse")
+				.short("r")
+				.long("reverse")
+				.help("Reverse the sort order"),
+		)
+		.arg(
+			Arg::with_name("long")
+				.short("L")
+				.long("long")
+				.help("Show all metadata columns (toggle columns with ctrl-t)"),
+		)
+		.arg(
+			Arg::with_name("perms")
+				.long("perms")
+				.help("Show a permissions column"),
+		)
+		.arg(
+			Arg::with_name("owner")
+				.long("owner")
+				.help("Show an owner column"),
+		)
+		.arg(
+			Arg::with_name("size")
+				.long("size")
+				.help("Show a size column"),
+		)
+		.arg(
+			Arg::with_name("mtime")
+				.long("mtime")
+				.help("Show a modification time column"),
+		)
+		.arg(
+			Arg::with_name("dir_sizes")
+				.long("dir-sizes")
+				.help("Show the total size of the files below each directory"),
+		)
+		.arg(
+			Arg::with_name("du")
+				.long("du")
+				.help("Show directory sizes, largest first, totalled over the matched files"),
+		)
+		.arg(
+			Arg::with_name("follow")
+				.long("follow")
+				.help("Follow symlinks, so that linked directories can be expanded"),
+		)
+		.arg(
+			Arg::with_name("icons")
+				.long("icons")
+				.value_name("WHEN")
+				.help("Show file icons, which need a Nerd Font, rather than ASCII markers")
+				.possible_values(&["never", "always", "auto"])
+				.default_value("never")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("color")
+				.long("color")
+				.value_name("SPEC")
+				.help(
+					"Colors as a comma separated list of a base theme (dark, light, 16 or bw) \
+					 and PART:COLOR pairs, where PART is one of fg, bg, bg+, hl, prompt, info, \
+					 pointer, marker or broken and COLOR is -1, 0-255, #rrggbb or a name",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("tree_style")
+				.long("tree-style")
+				.value_name("STYLE")
+				.help("Lines to draw between directories and their children")
+				.possible_values(&["unicode", "ascii", "rounded", "indent-only"])
+				.default_value("unicode")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("indent")
+				.long("indent")
+				.value_name("N")
+				.help("Number of characters to indent each level of the tree by")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("filter")
+				.short("f")
+				.long("filter")
+				.value_name("QUERY")
+				.help(
+					"Print the paths matching QUERY and exit, with status 1 if nothing \
+					 matched. Candidates are read from stdin if it isn't a terminal",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("flat")
+				.long("flat")
+				.requires("filter")
+				.help("Print filtered paths one per line, rather than as a tree"),
+		)
+		.arg(
+			Arg::with_name("print_tree")
+				.short("p")
+				.long("print-tree")
+				.value_name("QUERY")
+				.help("Print the tree, optionally filtered by QUERY, and exit")
+				.conflicts_with("filter")
+				.min_values(0)
+				.max_values(1),
+		)
+		.arg(
+			Arg::with_name("max_depth")
+				.short("d")
+				.long("max-depth")
 				.value_name("N")
-				.help("Max number of lines to use")
+				.requires("print_tree")
+				.help("Show at most N levels below the root when printing the tree")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("export")
+				.long("export")
+				.value_name("FORMAT")
+				.help(
+					"Write the tree to stdout in FORMAT on exit, or instead of printing it. \
+					 JSON includes all paths with their metadata, the others only matches",
+				)
+				.possible_values(&["json", "markdown", "html"])
+				.conflicts_with("flat")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("preview")
+				.long("preview")
+				.value_name("CMD")
+				.help(
+					"Preview the path under the cursor with the output of CMD, where {} is \
+					 replaced by the path. Without CMD, files show their first lines and \
+					 directories their contents (toggle with ctrl-p, scroll with alt-j/k/d/u)",
+				)
+				.min_values(0)
+				.max_values(1),
+		)
+		.arg(
+			Arg::with_name("preview_window")
+				.long("preview-window")
+				.value_name("POSITION")
+				.help("Where to show the preview relative to the tree")
+				.possible_values(&["right", "down"])
+				.default_value("right")
 				.takes_value(true),
 		)
 		.get_matches();
 
+	let du = matches.is_present("du");
+	let follow = matches.is_present("follow");
+
 	Args {
-		cmd: matches.value_of("cmd").unwrap_or(default_cmd()).to_string(),
+		cmd: matches
+			.value_of("cmd")
+			.unwrap_or_else(|| default_cmd(follow))
+			.to_string(),
 		n_collapse: parse_usize(matches.value_of("n_collapse"), "n_collapse", 0).unwrap_or(10),
-		n_lines: parse_usize(matches.value_of("n_lines"), "n_lines", 3).unwrap_or(20),
+		layout: Layout {
+			height: matches.value_of("height").map_or(Height::default(), |h| {
+				h.parse().unwrap_or_else(|e| {
+					utils::exit(&format!("invalid value for option '--height': {}", e))
+				})
+			}),
+			fullscreen: matches.is_present("fullscreen"),
+			reverse: matches.value_of("layout") == Some("reverse"),
+			prompt: matches
+				.value_of("prompt_position")
+				.unwrap()
+				.parse()
+				.unwrap(),
+			preview: if matches.is_present("preview") {
+				Some(matches.value_of("preview_window").unwrap().parse().unwrap())
+			} else {
+				None
+			},
+		},
+		threads: parse_usize(matches.value_of("threads"), "threads", 1)
+			.unwrap_or_else(default_threads),
+		compact_dirs: matches.is_present("compact_dirs"),
+		sort_by: matches
+			.value_of("sort")
+			.map_or(if du { SortBy::Size } else { SortBy::default() }, |s| {
+				s.parse().unwrap()
+			}),
+		reverse: matches.is_present("reverse"),
+		columns: columns(
+			matches.is_present("long"),
+			matches.is_present("perms"),
+			matches.is_present("owner"),
+			du || matches.is_present("size"),
+			matches.is_present("mtime"),
+			du || matches.is_present("dir_sizes"),
+		),
+		du,
+		follow,
+		icons: style::icons_enabled(matches.value_of("icons").unwrap()),
+		guides: Guides::new(
+			TreeStyle::parse(matches.value_of("tree_style").unwrap()).unwrap(),
+			parse_usize(matches.value_of("indent"), "indent", 1).unwrap_or(4),
+		),
+		filter: matches.value_of("filter").map(String::from),
+		flat: matches.is_present("flat"),
+		print_tree: if matches.is_present("print_tree") {
+			Some(matches.value_of("print_tree").unwrap_or("").to_string())
+		} else {
+			None
+		},
+		max_depth: parse_usize(matches.value_of("max_depth"), "max-depth", 1),
+		export: matches.value_of("export").map(|f| f.parse().unwrap()),
+		preview: if matches.is_present("preview") {
+			Some(matches.value_of("preview").unwrap_or("").to_string())
+		} else {
+			None
+		},
+		mouse: !matches.is_present("no_mouse"),
+		bindings: bindings(matches.values_of("bind").into_iter().flatten()),
+		theme: Theme::parse(matches.value_of("color").unwrap_or(""))
+			.unwrap_or_else(|e| utils::exit(&format!("invalid value for option '--color': {}", e))),
 	}
 }
 
-/// Get the default command to use. We naively assume that `fd` is the rust
-/// fd-find binary.
-#[cfg_attr(tarpaulin, skip)]
-fn default_cmd() -> &'static str {
-	if which::which("fd").is_ok() {
-		"fd"
+/// The default key bindings, changed by those in the config file and then by
+/// each of `specs` given with `--bind`.
+fn bindings<'a>(specs: impl Iterator<Item = &'a str>) -> Bindings {
+	let mut bindings = Bindings::default();
+	if let Some(path) = config::path() {
+		let config = config::read(&path)
+			.map_err(|e| e.to_string())
+			.and_then(|text| Config::parse(&text))
+			.unwrap_or_else(|e| utils::exit(&format!("{}: {}", path.display(), e)));
+		for entry in config.bind {
+			if let Err(e) = bindings.bind(&entry.key, &entry.value) {
+				utils::exit(&format!("{}: line {}: {}", path.display(), entry.line, e));
+			}
+		}
+	}
+	for spec in specs {
+		if let Err(e) = bindings.parse(spec) {
+			utils::exit(&format!("invalid value for option '--bind': {}", e));
+		}
+	}
+	bindings
+}
+
+/// Get the metadata columns to show. When none are asked for, all columns can
+/// still be toggled on.
+fn columns(
+	long: bool,
+	perms: bool,
+	owner: bool,
+	size: bool,
+	mtime: bool,
+	dir_sizes: bool,
+) -> Columns {
+	let columns = if long || !(perms || owner || size || mtime) {
+		Columns {
+			shown: long,
+			..Columns::all()
+		}
 	} else {
-		"find"
+		Columns {
+			perms,
+			owner,
+			size,
+			mtime,
+			dir_sizes: false,
+			shown: true,
+		}
+	};
+	Columns {
+		dir_sizes,
+		..columns
+	}
+}
+
+/// Get the default number of matching threads, one per available CPU.
+#[cfg_attr(tarpaulin, skip)]
+fn default_threads() -> usize {
+	thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Get the default command to use, which descends into linked directories if
+/// `follow`. We naively assume that `fd` is the rust fd-find binary.
+#[cfg_attr(tarpaulin, skip)]
+fn default_cmd(follow: bool) -> &'static str {
+	match (which::which("fd").is_ok(), follow) {
+		(true, false) => "fd",
+		(true, true) => "fd --follow",
+		(false, false) => "find",
+		(false, true) => "find -L",
 	}
 }
 
@@ -91,4 +467,19 @@ mod test {
 	fn parsing_usize_with_ok_value() {
 		assert_eq!(parse_usize(Some("5"), "lines", 3), Some(5));
 	}
+
+	#[test]
+	fn columns_from_flags() {
+		let cols = columns(false, false, false, false, false, false);
+		assert_eq!(
+			cols,
+			Columns {
+				shown: false,
+				..Columns::all()
+			}
+		);
+
+		let cols = columns(false, false, false, true, false, true);
+		assert!(cols.shown && cols.size && cols.dir_sizes && !cols.perms);
+	}
 }
@@ -0,0 +1,337 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use termion::event::Key;
+
+/// Something which a key can be bound to.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Action {
+	Abort,
+	Accept,
+	/// Flip the selection of the path under the cursor and move down
+	Select,
+	ToggleOpen,
+	ExpandAll,
+	CollapseAll,
+	Up,
+	Down,
+	PageUp,
+	PageDown,
+	BackwardChar,
+	ForwardChar,
+	BeginningOfLine,
+	EndOfLine,
+	BackwardDeleteChar,
+	DeleteChar,
+	/// Cut the input before the cursor
+	UnixLineDiscard,
+	/// Cut the word before the cursor
+	UnixWordRubout,
+	/// Paste what was last cut
+	Yank,
+	CycleSort,
+	ToggleColumns,
+	TogglePreview,
+	ScrollLeft,
+	ScrollRight,
+	PreviewUp,
+	PreviewDown,
+	PreviewHalfPageUp,
+	PreviewHalfPageDown,
+	/// Do nothing, e.g. to unbind a key
+	Ignore,
+}
+
+impl Action {
+	pub const ALL: [Action; 29] = [
+		Action::Abort,
+		Action::Accept,
+		Action::Select,
+		Action::ToggleOpen,
+		Action::ExpandAll,
+		Action::CollapseAll,
+		Action::Up,
+		Action::Down,
+		Action::PageUp,
+		Action::PageDown,
+		Action::BackwardChar,
+		Action::ForwardChar,
+		Action::BeginningOfLine,
+		Action::EndOfLine,
+		Action::BackwardDeleteChar,
+		Action::DeleteChar,
+		Action::UnixLineDiscard,
+		Action::UnixWordRubout,
+		Action::Yank,
+		Action::CycleSort,
+		Action::ToggleColumns,
+		Action::TogglePreview,
+		Action::ScrollLeft,
+		Action::ScrollRight,
+		Action::PreviewUp,
+		Action::PreviewDown,
+		Action::PreviewHalfPageUp,
+		Action::PreviewHalfPageDown,
+		Action::Ignore,
+	];
+
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Action::Abort => "abort",
+			Action::Accept => "accept",
+			Action::Select => "select",
+			Action::ToggleOpen => "toggle-open",
+			Action::ExpandAll => "expand-all",
+			Action::CollapseAll => "collapse-all",
+			Action::Up => "up",
+			Action::Down => "down",
+			Action::PageUp => "page-up",
+			Action::PageDown => "page-down",
+			Action::BackwardChar => "backward-char",
+			Action::ForwardChar => "forward-char",
+			Action::BeginningOfLine => "beginning-of-line",
+			Action::EndOfLine => "end-of-line",
+			Action::BackwardDeleteChar => "backward-delete-char",
+			Action::DeleteChar => "delete-char",
+			Action::UnixLineDiscard => "unix-line-discard",
+			Action::UnixWordRubout => "unix-word-rubout",
+			Action::Yank => "yank",
+			Action::CycleSort => "cycle-sort",
+			Action::ToggleColumns => "toggle-columns",
+			Action::TogglePreview => "toggle-preview",
+			Action::ScrollLeft => "scroll-left",
+			Action::ScrollRight => "scroll-right",
+			Action::PreviewUp => "preview-up",
+			Action::PreviewDown => "preview-down",
+			Action::PreviewHalfPageUp => "preview-half-page-up",
+			Action::PreviewHalfPageDown => "preview-half-page-down",
+			Action::Ignore => "ignore",
+		}
+	}
+}
+
+impl fmt::Display for Action {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl FromStr for Action {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Action::ALL
+			.iter()
+			.find(|action| action.as_str() == s)
+			.copied()
+			.ok_or_else(|| format!("unknown action: {}", s))
+	}
+}
+
+/// Keys which are named rather than written as the character they type.
+const NAMED_KEYS: [(&str, Key); 17] = [
+	("enter", Key::Char('\n')),
+	("tab", Key::Char('\t')),
+	("space", Key::Char(' ')),
+	("comma", Key::Char(',')),
+	("btab", Key::BackTab),
+	("esc", Key::Esc),
+	("bspace", Key::Backspace),
+	("del", Key::Delete),
+	("insert", Key::Insert),
+	("up", Key::Up),
+	("down", Key::Down),
+	("left", Key::Left),
+	("right", Key::Right),
+	("home", Key::Home),
+	("end", Key::End),
+	("pgup", Key::PageUp),
+	("pgdn", Key::PageDown),
+];
+
+/// Parse the name of a key, e.g. `ctrl-n`, `alt-h`, `f2`, `enter` or `` ` ``.
+/// As a terminal can't tell them apart from `enter` and `tab`, `ctrl-j`,
+/// `ctrl-m` and `ctrl-i` are refused.
+pub fn parse_key(s: &str) -> Result<Key, String> {
+	let single = |s: &str| {
+		let mut chars = s.chars();
+		chars.next().filter(|_| chars.next().is_none())
+	};
+	let key = if let Some((_, key)) = NAMED_KEYS.iter().find(|(name, _)| *name == s) {
+		Some(*key)
+	} else if let Some(c) = s.strip_prefix("ctrl-").and_then(single) {
+		// Terminals send these as the same bytes as enter and tab
+		let same = match c {
+			'j' | 'm' => Some("enter"),
+			'i' => Some("tab"),
+			_ => None,
+		};
+		if let Some(name) = same {
+			return Err(format!("{} is the same key as {}, bind {1}", s, name));
+		}
+		Some(Key::Ctrl(c)).filter(|_| c.is_ascii_lowercase())
+	} else if let Some(c) = s.strip_prefix("alt-").and_then(single) {
+		Some(Key::Alt(c)).filter(|_| !c.is_control())
+	} else if let Some(n) = s.strip_prefix('f').and_then(|n| n.parse().ok()) {
+		Some(Key::F(n)).filter(|_| (1..=12).contains(&n))
+	} else {
+		single(s).filter(|c| !c.is_control()).map(Key::Char)
+	};
+
+	match key {
+		// Always leave a way out, whatever is bound
+		Some(Key::Ctrl('c')) => Err("ctrl-c can't be rebound".to_string()),
+		Some(key) => Ok(key),
+		None => Err(format!("unknown key: {}", s)),
+	}
+}
+
+/// The action bound to each key. Characters which aren't bound are typed
+/// into the prompt.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Bindings(HashMap<Key, Action>);
+
+impl Default for Bindings {
+	fn default() -> Self {
+		Bindings(
+			[
+				(Key::Esc, Action::Abort),
+				(Key::Char('\n'), Action::Accept),
+				(Key::Char('\t'), Action::Select),
+				(Key::Char('`'), Action::ToggleOpen),
+				(Key::Up, Action::Up),
+				(Key::Down, Action::Down),
+				(Key::PageUp, Action::PageUp),
+				(Key::PageDown, Action::PageDown),
+				(Key::Left, Action::BackwardChar),
+				(Key::Right, Action::ForwardChar),
+				(Key::Home, Action::BeginningOfLine),
+				(Key::End, Action::EndOfLine),
+				(Key::Backspace, Action::BackwardDeleteChar),
+				(Key::Delete, Action::DeleteChar),
+				(Key::Ctrl('u'), Action::UnixLineDiscard),
+				(Key::Ctrl('w'), Action::UnixWordRubout),
+				(Key::Ctrl('y'), Action::Yank),
+				(Key::Ctrl('s'), Action::CycleSort),
+				(Key::Ctrl('t'), Action::ToggleColumns),
+				(Key::Ctrl('p'), Action::TogglePreview),
+				(Key::Alt('h'), Action::ScrollLeft),
+				(Key::Alt('l'), Action::ScrollRight),
+				(Key::Alt('j'), Action::PreviewDown),
+				(Key::Alt('k'), Action::PreviewUp),
+				(Key::Alt('d'), Action::PreviewHalfPageDown),
+				(Key::Alt('u'), Action::PreviewHalfPageUp),
+			]
+			.iter()
+			.copied()
+			.collect(),
+		)
+	}
+}
+
+impl Bindings {
+	/// Bind `key` to `action`, both given by name.
+	pub fn bind(&mut self, key: &str, action: &str) -> Result<(), String> {
+		let key = parse_key(key.trim())?;
+		let action = action.trim().parse()?;
+		self.0.insert(key, action);
+		Ok(())
+	}
+
+	/// Apply bindings in the form `KEY:ACTION[,KEY:ACTION...]`.
+	pub fn parse(&mut self, spec: &str) -> Result<(), String> {
+		for binding in spec.split(',').filter(|b| !b.is_empty()) {
+			// The key may itself be a colon
+			let first = binding.chars().next().map_or(0, char::len_utf8);
+			let (key, action) = binding[first..]
+				.find(':')
+				.map(|i| binding.split_at(first + i))
+				.ok_or_else(|| format!("expected KEY:ACTION, got: {}", binding))?;
+			self.bind(key, &action[1..])?;
+		}
+		Ok(())
+	}
+
+	/// The action bound to `key`, if any.
+	pub fn get(&self, key: Key) -> Option<Action> {
+		self.0.get(&key).copied()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parsing_actions() {
+		for action in Action::ALL.iter() {
+			assert_eq!(action.as_str().parse(), Ok(*action));
+		}
+		assert!("jump".parse::<Action>().is_err());
+	}
+
+	#[test]
+	fn parsing_keys() {
+		assert_eq!(parse_key("ctrl-n"), Ok(Key::Ctrl('n')));
+		assert_eq!(parse_key("alt-H"), Ok(Key::Alt('H')));
+		assert_eq!(parse_key("enter"), Ok(Key::Char('\n')));
+		assert_eq!(parse_key("pgdn"), Ok(Key::PageDown));
+		assert_eq!(parse_key("comma"), Ok(Key::Char(',')));
+		assert_eq!(parse_key("f12"), Ok(Key::F(12)));
+		assert_eq!(parse_key("`"), Ok(Key::Char('`')));
+		assert_eq!(parse_key("é"), Ok(Key::Char('é')));
+		assert!(parse_key("f13").is_err());
+		assert!(parse_key("ctrl-").is_err());
+		assert!(parse_key("ctrl-J").is_err());
+		assert!(parse_key("hyper-x").is_err());
+		assert!(parse_key("ctrl-c").is_err());
+		assert_eq!(
+			parse_key("ctrl-j"),
+			Err("ctrl-j is the same key as enter, bind enter".to_string())
+		);
+		assert_eq!(
+			parse_key("ctrl-m"),
+			Err("ctrl-m is the same key as enter, bind enter".to_string())
+		);
+		assert_eq!(
+			parse_key("ctrl-i"),
+			Err("ctrl-i is the same key as tab, bind tab".to_string())
+		);
+	}
+
+	#[test]
+	fn rebinding_keys() {
+		let mut bindings = Bindings::default();
+		bindings
+			.parse("ctrl-n:down,ctrl-k:up,tab:ignore,::expand-all,é:collapse-all")
+			.unwrap();
+		assert_eq!(bindings.get(Key::Ctrl('n')), Some(Action::Down));
+		assert_eq!(bindings.get(Key::Ctrl('k')), Some(Action::Up));
+		assert_eq!(bindings.get(Key::Char('\t')), Some(Action::Ignore));
+		assert_eq!(bindings.get(Key::Char(':')), Some(Action::ExpandAll));
+		assert_eq!(bindings.get(Key::Char('é')), Some(Action::CollapseAll));
+		// Other bindings are left alone
+		assert_eq!(bindings.get(Key::Char('\n')), Some(Action::Accept));
+		assert_eq!(bindings.get(Key::Char('a')), None);
+	}
+
+	#[test]
+	fn invalid_bindings() {
+		let mut bindings = Bindings::default();
+		assert_eq!(
+			bindings.parse("ctrl-n:jump"),
+			Err("unknown action: jump".to_string())
+		);
+		assert_eq!(
+			bindings.parse("meta-j:down"),
+			Err("unknown key: meta-j".to_string())
+		);
+		assert!(bindings.parse("down").is_err());
+		assert!(bindings.parse(":").is_err());
+	}
+}
@@ -0,0 +1,196 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+use crate::path::Path;
+use chrono::{DateTime, Local};
+use std::cell::RefCell;
+use users::{Users, UsersCache};
+
+const SIZE_WIDTH: usize = 5;
+const OWNER_WIDTH: usize = 8;
+/// How modification times are shown.
+pub const MTIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+const MTIME_WIDTH: usize = 16;
+
+thread_local! {
+	static USERS: RefCell<UsersCache> = RefCell::new(UsersCache::new());
+}
+
+/// Metadata columns which can be shown alongside each path.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Columns {
+	pub perms: bool,
+	pub owner: bool,
+	pub size: bool,
+	pub mtime: bool,
+	/// Show the total size of all files below a directory, rather than `-`
+	pub dir_sizes: bool,
+	/// Whether the enabled columns are currently displayed
+	pub shown: bool,
+}
+
+impl Columns {
+	/// All columns, which are shown.
+	pub fn all() -> Self {
+		Columns {
+			perms: true,
+			owner: true,
+			size: true,
+			mtime: true,
+			dir_sizes: false,
+			shown: true,
+		}
+	}
+
+	fn any(&self) -> bool {
+		self.perms || self.owner || self.size || self.mtime
+	}
+
+	pub fn toggle(&mut self) {
+		self.shown = !self.shown;
+	}
+
+	/// Whether any columns are displayed.
+	pub fn is_shown(&self) -> bool {
+		self.shown && self.any()
+	}
+
+	/// Format the enabled columns for `pth`, each after a space separating it
+	/// from the tree or the column before. Empty if no columns are shown.
+	pub fn format(&self, pth: &Path) -> String {
+		if !self.is_shown() {
+			return String::new();
+		}
+
+		let mut s = String::new();
+		if self.perms {
+			s.push(' ');
+			s += &permissions(pth.mode);
+		}
+		if self.owner {
+			s += &format!(" {:<w$.w$}", owner(pth.uid), w = OWNER_WIDTH);
+		}
+		if self.size {
+			let size = if !pth.is_dir {
+				human_size(pth.size)
+			} else if self.dir_sizes {
+				human_size(pth.total_size)
+			} else {
+				"-".to_string()
+			};
+			s += &format!(" {:>w$}", size, w = SIZE_WIDTH);
+		}
+		if self.mtime {
+			let mtime = pth.modified.map_or_else(String::new, |t| {
+				DateTime::<Local>::from(t).format(MTIME_FORMAT).to_string()
+			});
+			s += &format!(" {:>w$}", mtime, w = MTIME_WIDTH);
+		}
+		s
+	}
+}
+
+/// Format a size in bytes with a binary unit suffix, e.g. `4.0K`.
+pub fn human_size(bytes: u64) -> String {
+	const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+	if bytes < 1024 {
+		return bytes.to_string();
+	}
+
+	let mut size = bytes as f64 / 1024.0;
+	let mut unit = 0;
+	while size >= 1023.95 && unit < UNITS.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+	if size < 9.95 {
+		format!("{:.1}{}", size, UNITS[unit])
+	} else {
+		format!("{:.0}{}", size, UNITS[unit])
+	}
+}
+
+/// Format unix mode bits like `ls -l`, e.g. `drwxr-xr-x`. Paths without
+/// metadata are shown as dashes.
+pub fn permissions(mode: u32) -> String {
+	let kind = match mode & 0o170000 {
+		0o040000 => 'd',
+		0o120000 => 'l',
+		0o010000 => 'p',
+		0o140000 => 's',
+		0o020000 => 'c',
+		0o060000 => 'b',
+		_ => '-',
+	};
+
+	let mut s = String::with_capacity(10);
+	s.push(kind);
+	for shift in [6, 3, 0].iter() {
+		let bits = mode >> shift;
+		s.push(if bits & 4 != 0 { 'r' } else { '-' });
+		s.push(if bits & 2 != 0 { 'w' } else { '-' });
+		s.push(if bits & 1 != 0 { 'x' } else { '-' });
+	}
+	s
+}
+
+/// The name of the user with `uid`, or the uid itself if there is no such
+/// user.
+pub fn owner(uid: u32) -> String {
+	USERS.with(|users| match users.borrow().get_user_by_uid(uid) {
+		Some(user) => user.name().to_string_lossy().into_owned(),
+		None => uid.to_string(),
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::path::Meta;
+
+	#[test]
+	fn human_sizes() {
+		assert_eq!(human_size(0), "0");
+		assert_eq!(human_size(1023), "1023");
+		assert_eq!(human_size(1024), "1.0K");
+		assert_eq!(human_size(15 * 1024 + 100), "15K");
+		assert_eq!(human_size(1024 * 1024 - 1), "1.0M");
+		assert_eq!(human_size(3 * 1024 * 1024 * 1024), "3.0G");
+	}
+
+	#[test]
+	fn permission_bits() {
+		assert_eq!(permissions(0o040755), "drwxr-xr-x");
+		assert_eq!(permissions(0o100644), "-rw-r--r--");
+		assert_eq!(permissions(0o120777), "lrwxrwxrwx");
+		assert_eq!(permissions(0), "----------");
+	}
+
+	#[test]
+	fn formatting_columns() {
+		let pth = Path::new(
+			"a".into(),
+			Meta {
+				is_dir: true,
+				size: 4096,
+				mode: 0o040700,
+				..Meta::default()
+			},
+		);
+		pth.borrow_mut().total_size = 2048;
+
+		let mut columns = Columns {
+			perms: true,
+			size: true,
+			shown: true,
+			..Columns::default()
+		};
+		assert_eq!(columns.format(&pth.borrow()), " drwx------     -");
+		columns.dir_sizes = true;
+		assert_eq!(columns.format(&pth.borrow()), " drwx------  2.0K");
+		columns.toggle();
+		assert_eq!(columns.format(&pth.borrow()), "");
+	}
+}
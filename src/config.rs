@@ -0,0 +1,138 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A setting in the config file, with the number of the line it is on.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Entry {
+	pub line: usize,
+	pub key: String,
+	pub value: String,
+}
+
+/// Settings read from the config file, which is split into sections of
+/// `KEY = VALUE` lines, e.g.
+///
+/// ```text
+/// # Move with ctrl-n/k
+/// [bind]
+/// ctrl-n = down
+/// ctrl-k = up
+/// ```
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Config {
+	/// Keys and the actions they are bound to
+	pub bind: Vec<Entry>,
+}
+
+impl Config {
+	/// Parse the contents of a config file. Errors give the line number.
+	pub fn parse(text: &str) -> Result<Config, String> {
+		let mut config = Config::default();
+		let mut section = None;
+
+		for (i, line) in text.lines().enumerate() {
+			let line = line.trim();
+			let n = i + 1;
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+				section = match name.trim() {
+					"bind" => Some(&mut config.bind),
+					name => return Err(format!("line {}: unknown section: {}", n, name)),
+				};
+				continue;
+			}
+
+			let entries = section
+				.as_mut()
+				.ok_or_else(|| format!("line {}: expected a section such as [bind]", n))?;
+			// The key may itself be an equals sign
+			let first = line.chars().next().map_or(0, char::len_utf8);
+			let (key, value) = line[first..]
+				.find('=')
+				.map(|j| line.split_at(first + j))
+				.ok_or_else(|| format!("line {}: expected KEY = VALUE", n))?;
+			entries.push(Entry {
+				line: n,
+				key: key.trim().to_string(),
+				value: value[1..].trim().to_string(),
+			});
+		}
+		Ok(config)
+	}
+}
+
+/// Where the config file is looked for, `$XDG_CONFIG_HOME/fztree/config` or
+/// `~/.config/fztree/config`.
+pub fn path() -> Option<PathBuf> {
+	let dir = match env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+		Some(dir) => PathBuf::from(dir),
+		None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+	};
+	Some(dir.join("fztree").join("config"))
+}
+
+/// Read the config file at `path`, which is empty if it doesn't exist.
+pub fn read(path: &Path) -> Result<String, io::Error> {
+	match fs::read_to_string(path) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+		result => result,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn entry(line: usize, key: &str, value: &str) -> Entry {
+		Entry {
+			line,
+			key: key.to_string(),
+			value: value.to_string(),
+		}
+	}
+
+	#[test]
+	fn parsing_config() {
+		let config = Config::parse("# keys\n\n[bind]\nctrl-n = down\n  = = up\nalt-x=ignore\n");
+		assert_eq!(
+			config.unwrap().bind,
+			vec![
+				entry(4, "ctrl-n", "down"),
+				entry(5, "=", "up"),
+				entry(6, "alt-x", "ignore"),
+			]
+		);
+		assert_eq!(Config::parse(""), Ok(Config::default()));
+	}
+
+	#[test]
+	fn invalid_config() {
+		assert_eq!(
+			Config::parse("[keys]\n"),
+			Err("line 1: unknown section: keys".to_string())
+		);
+		assert_eq!(
+			Config::parse("ctrl-n = down\n"),
+			Err("line 1: expected a section such as [bind]".to_string())
+		);
+		assert_eq!(
+			Config::parse("[bind]\n\nctrl-n down\n"),
+			Err("line 3: expected KEY = VALUE".to_string())
+		);
+	}
+
+	#[test]
+	fn missing_config_is_empty() {
+		let dir = tempfile::tempdir().unwrap();
+		assert_eq!(read(&dir.path().join("config")).unwrap(), "");
+	}
+}
@@ -0,0 +1,294 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+use crate::columns::{owner, permissions};
+use crate::path::ArcPath;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+
+/// Structured formats which the tree can be written in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+	/// Nested objects with all metadata, for other tools
+	Json,
+	/// A nested list of the matched paths
+	Markdown,
+	/// A list of the matched paths with collapsible `<details>` directories
+	Html,
+}
+
+impl FromStr for Format {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"json" => Ok(Format::Json),
+			"markdown" | "md" => Ok(Format::Markdown),
+			"html" => Ok(Format::Html),
+			_ => Err(format!("unknown export format: {}", s)),
+		}
+	}
+}
+
+/// Write the tree below `root` to `out` in `format`. JSON includes every
+/// path, along with whether it is matched, while the other formats only
+/// include matched paths.
+pub fn export<W: Write>(root: &ArcPath, format: Format, out: &mut W) -> io::Result<()> {
+	match format {
+		Format::Json => {
+			json(root, out)?;
+			writeln!(out)
+		}
+		Format::Markdown => markdown(root, 0, out),
+		Format::Html => {
+			writeln!(out, "<ul class=\"fztree\">")?;
+			html(root, out)?;
+			writeln!(out, "</ul>")
+		}
+	}
+}
+
+fn matched_children(node: &ArcPath) -> Vec<ArcPath> {
+	node.borrow()
+		.children
+		.iter()
+		.flatten()
+		.filter(|c| c.borrow().matched)
+		.cloned()
+		.collect()
+}
+
+fn json<W: Write>(node: &ArcPath, out: &mut W) -> io::Result<()> {
+	let pth = node.borrow();
+	// Paths without metadata have no mode
+	let (perms, owner) = if pth.mode == 0 {
+		("null".to_string(), "null".to_string())
+	} else {
+		(json_str(&permissions(pth.mode)), json_str(&owner(pth.uid)))
+	};
+	let modified = pth
+		.modified
+		.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+		.map_or_else(|| "null".to_string(), |d| d.as_secs().to_string());
+	let link = pth.link.as_ref().map_or_else(
+		|| "null".to_string(),
+		|l| {
+			format!(
+				"{{\"target\":{},\"broken\":{}}}",
+				json_str(&l.target),
+				l.broken
+			)
+		},
+	);
+
+	write!(
+		out,
+		"{{\"name\":{},\"path\":{},\"is_dir\":{},\"size\":{},\"total_size\":{},\
+		 \"modified\":{},\"permissions\":{},\"owner\":{},\"link\":{},\
+		 \"matched\":{},\"selected\":{}",
+		json_str(&pth.name),
+		json_str(&node.joined()),
+		pth.is_dir,
+		pth.size,
+		pth.total_size,
+		modified,
+		perms,
+		owner,
+		link,
+		pth.matched,
+		pth.selected,
+	)?;
+	if let Some(children) = &pth.children {
+		write!(out, ",\"children\":[")?;
+		for (i, child) in children.iter().enumerate() {
+			if i > 0 {
+				write!(out, ",")?;
+			}
+			json(child, out)?;
+		}
+		write!(out, "]")?;
+	}
+	write!(out, "}}")
+}
+
+fn json_str(s: &str) -> String {
+	let mut escaped = String::with_capacity(s.len() + 2);
+	escaped.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => escaped.push_str("\\\""),
+			'\\' => escaped.push_str("\\\\"),
+			'\n' => escaped.push_str("\\n"),
+			'\r' => escaped.push_str("\\r"),
+			'\t' => escaped.push_str("\\t"),
+			c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+			c => escaped.push(c),
+		}
+	}
+	escaped.push('"');
+	escaped
+}
+
+/// The name shown for a path in Markdown and HTML, with a trailing `/` for
+/// directories and the target of symlinks.
+fn label(node: &ArcPath) -> String {
+	let is_root = node.is_root();
+	let pth = node.borrow();
+	let mut label = pth.name.to_string();
+	if pth.is_dir && !is_root {
+		label.push('/');
+	}
+	if let Some(link) = &pth.link {
+		label.push_str(" -> ");
+		label.push_str(&link.target);
+	}
+	label
+}
+
+fn markdown<W: Write>(node: &ArcPath, depth: usize, out: &mut W) -> io::Result<()> {
+	writeln!(out, "{}- {}", "  ".repeat(depth), code_span(&label(node)))?;
+	for child in matched_children(node) {
+		markdown(&child, depth + 1, out)?;
+	}
+	Ok(())
+}
+
+/// Wrap `s` in a Markdown code span, fenced by a longer run of backticks than
+/// any it contains.
+fn code_span(s: &str) -> String {
+	let longest = s.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+	let fence = "`".repeat(longest + 1);
+	let pad = if s.starts_with('`') || s.ends_with('`') {
+		" "
+	} else {
+		""
+	};
+	format!("{}{}{}{}{}", fence, pad, s, pad, fence)
+}
+
+fn html<W: Write>(node: &ArcPath, out: &mut W) -> io::Result<()> {
+	let label = html_escape(&label(node));
+	let (is_dir, open) = {
+		let pth = node.borrow();
+		(pth.is_dir, pth.open)
+	};
+	if !is_dir {
+		return writeln!(out, "<li>{}</li>", label);
+	}
+
+	writeln!(
+		out,
+		"<li><details{}><summary>{}</summary>",
+		if open { " open" } else { "" },
+		label
+	)?;
+	let children = matched_children(node);
+	if !children.is_empty() {
+		writeln!(out, "<ul>")?;
+		for child in &children {
+			html(child, out)?;
+		}
+		writeln!(out, "</ul>")?;
+	}
+	writeln!(out, "</details></li>")
+}
+
+fn html_escape(s: &str) -> String {
+	s.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::path::{build_paths, Link, Meta};
+	use crate::tree::Tree;
+
+	fn exported(tree: &Tree, format: Format) -> String {
+		let mut out = Vec::new();
+		export(&tree.tree, format, &mut out).unwrap();
+		String::from_utf8(out).unwrap()
+	}
+
+	fn create_tree() -> Tree {
+		let link = Meta {
+			link: Some(Link {
+				target: "../x<y".into(),
+				broken: true,
+			}),
+			..Meta::default()
+		};
+		let paths = build_paths(vec![
+			("src", Meta::from(true)),
+			("src/a\"b.rs", Meta::from(false)),
+			("src/l", link),
+			("README", Meta::from(false)),
+		]);
+		Tree::from_paths(paths)
+	}
+
+	#[test]
+	fn escaping_json_strings() {
+		assert_eq!(json_str("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+	}
+
+	#[test]
+	fn exporting_json() {
+		let mut tree = create_tree();
+		tree.filter("rs");
+		let json = exported(&tree, Format::Json);
+		assert!(json.starts_with(
+			"{\"name\":\".\",\"path\":\".\",\"is_dir\":true,\"size\":0,\"total_size\":0,\
+			 \"modified\":null,\"permissions\":null,\"owner\":null,\"link\":null,\
+			 \"matched\":true,\"selected\":false,\"children\":[{\"name\":\"README\""
+		));
+		assert!(json.contains("\"name\":\"a\\\"b.rs\",\"path\":\"src/a\\\"b.rs\""));
+		assert!(json.contains("\"link\":{\"target\":\"../x<y\",\"broken\":true},\"matched\":false"));
+		assert!(json.ends_with("}]}]}\n"));
+	}
+
+	#[test]
+	fn exporting_markdown() {
+		let mut tree = create_tree();
+		assert_eq!(
+			exported(&tree, Format::Markdown),
+			"- `.`\n  - `README`\n  - `src/`\n    - `a\"b.rs`\n    - `l -> ../x<y`\n"
+		);
+
+		tree.filter("rs");
+		assert_eq!(
+			exported(&tree, Format::Markdown),
+			"- `.`\n  - `src/`\n    - `a\"b.rs`\n"
+		);
+	}
+
+	#[test]
+	fn fencing_code_spans() {
+		assert_eq!(code_span("a.rs"), "`a.rs`");
+		assert_eq!(code_span("a`b``c"), "```a`b``c```");
+		assert_eq!(code_span("`a"), "`` `a ``");
+	}
+
+	#[test]
+	fn exporting_html() {
+		let tree = create_tree();
+		tree.paths[2].flip_open();
+		assert_eq!(
+			exported(&tree, Format::Html),
+			"<ul class=\"fztree\">\n\
+			 <li><details open><summary>.</summary>\n<ul>\n\
+			 <li>README</li>\n\
+			 <li><details><summary>src/</summary>\n<ul>\n\
+			 <li>a&quot;b.rs</li>\n\
+			 <li>l -&gt; ../x&lt;y</li>\n\
+			 </ul>\n</details></li>\n\
+			 </ul>\n</details></li>\n\
+			 </ul>\n"
+		);
+	}
+}
@@ -0,0 +1,303 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+use crate::theme::{Color, Depth};
+use std::path;
+
+/// The parts of a language which are highlighted. This is deliberately
+/// simple, it only needs to be good enough for a preview.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Syntax {
+	line_comments: &'static [&'static str],
+	block_comment: Option<(&'static str, &'static str)>,
+	quotes: &'static [char],
+	keywords: &'static [&'static str],
+}
+
+const RUST: Syntax = Syntax {
+	line_comments: &["//"],
+	block_comment: Some(("/*", "*/")),
+	// Single quotes are also used for lifetimes
+	quotes: &['"'],
+	keywords: &[
+		"as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+		"extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+		"mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+		"true", "type", "unsafe", "use", "where", "while",
+	],
+};
+
+const C_LIKE: Syntax = Syntax {
+	line_comments: &["//"],
+	block_comment: Some(("/*", "*/")),
+	quotes: &['"', '\'', '`'],
+	keywords: &[
+		"break",
+		"case",
+		"catch",
+		"class",
+		"const",
+		"continue",
+		"default",
+		"do",
+		"else",
+		"enum",
+		"export",
+		"extends",
+		"false",
+		"final",
+		"for",
+		"func",
+		"function",
+		"go",
+		"if",
+		"implements",
+		"import",
+		"interface",
+		"let",
+		"new",
+		"null",
+		"package",
+		"private",
+		"protected",
+		"public",
+		"return",
+		"static",
+		"struct",
+		"switch",
+		"this",
+		"throw",
+		"true",
+		"try",
+		"typedef",
+		"var",
+		"void",
+		"while",
+	],
+};
+
+const PYTHON: Syntax = Syntax {
+	line_comments: &["#"],
+	block_comment: None,
+	quotes: &['"', '\''],
+	keywords: &[
+		"and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+		"elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import",
+		"in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return", "True",
+		"try", "while", "with", "yield",
+	],
+};
+
+const SHELL: Syntax = Syntax {
+	line_comments: &["#"],
+	block_comment: None,
+	quotes: &['"', '\''],
+	keywords: &[
+		"case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+		"in", "local", "return", "then", "until", "while",
+	],
+};
+
+const CONFIG: Syntax = Syntax {
+	line_comments: &["#", ";"],
+	block_comment: None,
+	quotes: &['"', '\''],
+	keywords: &["false", "true"],
+};
+
+const JSON: Syntax = Syntax {
+	line_comments: &[],
+	block_comment: None,
+	quotes: &['"'],
+	keywords: &["false", "null", "true"],
+};
+
+/// Guess the language of the file at `path` from its name.
+pub fn syntax_for(path: &str) -> Option<&'static Syntax> {
+	let path = path::Path::new(path);
+	let name = path.file_name()?.to_str()?;
+	if name == "Makefile" || name == "Dockerfile" {
+		return Some(&SHELL);
+	}
+
+	let extension = path.extension()?.to_str()?.to_lowercase();
+	match extension.as_str() {
+		"rs" => Some(&RUST),
+		"c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "cs" | "go" | "java" | "js" | "jsx" | "kt"
+		| "scala" | "swift" | "ts" | "tsx" => Some(&C_LIKE),
+		"py" | "pyi" => Some(&PYTHON),
+		"bash" | "fish" | "sh" | "zsh" => Some(&SHELL),
+		"cfg" | "conf" | "ini" | "toml" | "yaml" | "yml" => Some(&CONFIG),
+		"json" => Some(&JSON),
+		_ => None,
+	}
+}
+
+/// Highlights a file line by line, keeping track of block comments which span
+/// lines.
+pub struct Highlighter {
+	syntax: &'static Syntax,
+	in_comment: bool,
+	keyword: Color,
+	string: Color,
+	number: Color,
+	comment: Color,
+}
+
+impl Highlighter {
+	/// Highlight `syntax` with colours which a terminal of `depth` can show.
+	pub fn new(syntax: &'static Syntax, depth: Depth) -> Self {
+		Highlighter {
+			syntax,
+			in_comment: false,
+			keyword: Color::Basic(5).with_depth(depth),
+			string: Color::Basic(2).with_depth(depth),
+			number: Color::Basic(3).with_depth(depth),
+			comment: Color::Basic(8).with_depth(depth),
+		}
+	}
+
+	/// Highlight the next line of the file.
+	pub fn line(&mut self, line: &str) -> String {
+		let mut out = String::with_capacity(line.len());
+		let mut rest = line;
+
+		while !rest.is_empty() {
+			if self.in_comment {
+				let end = self.syntax.block_comment.map_or("", |(_, end)| end);
+				let len = match rest.find(end) {
+					Some(i) => {
+						self.in_comment = false;
+						i + end.len()
+					}
+					None => rest.len(),
+				};
+				paint(&mut out, self.comment, &rest[..len]);
+				rest = &rest[len..];
+				continue;
+			}
+
+			if let Some((start, _)) = self.syntax.block_comment {
+				if rest.starts_with(start) {
+					self.in_comment = true;
+					paint(&mut out, self.comment, start);
+					rest = &rest[start.len()..];
+					continue;
+				}
+			}
+			if self
+				.syntax
+				.line_comments
+				.iter()
+				.any(|c| rest.starts_with(c))
+			{
+				paint(&mut out, self.comment, rest);
+				break;
+			}
+
+			let c = rest.chars().next().unwrap();
+			let (color, len) = if self.syntax.quotes.contains(&c) {
+				(self.string, string_len(rest, c))
+			} else if c.is_ascii_digit() {
+				(
+					self.number,
+					word_len(rest, |c| c.is_alphanumeric() || c == '.'),
+				)
+			} else if c.is_alphabetic() || c == '_' {
+				let len = word_len(rest, |c| c.is_alphanumeric() || c == '_');
+				if self.syntax.keywords.contains(&&rest[..len]) {
+					(self.keyword, len)
+				} else {
+					(Color::Default, len)
+				}
+			} else {
+				(Color::Default, c.len_utf8())
+			};
+			paint(&mut out, color, &rest[..len]);
+			rest = &rest[len..];
+		}
+		out
+	}
+}
+
+fn paint(out: &mut String, color: Color, text: &str) {
+	out.push_str(&color.fg());
+	out.push_str(text);
+	out.push_str(color.fg_reset());
+}
+
+/// Length of the leading run of characters in `s` which satisfy `f`.
+fn word_len<F: Fn(char) -> bool>(s: &str, f: F) -> usize {
+	s.find(|c| !f(c)).unwrap_or(s.len())
+}
+
+/// Length of the string starting at the beginning of `s` which is quoted by
+/// `quote`, up to the end of the line if it isn't closed.
+fn string_len(s: &str, quote: char) -> usize {
+	let mut escaped = false;
+	for (i, c) in s.char_indices().skip(1) {
+		if escaped {
+			escaped = false;
+		} else if c == '\\' {
+			escaped = true;
+		} else if c == quote {
+			return i + c.len_utf8();
+		}
+	}
+	s.len()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	const KW: &str = "\u{1b}[35m";
+	const STR: &str = "\u{1b}[32m";
+	const NUM: &str = "\u{1b}[33m";
+	const COMMENT: &str = "\u{1b}[90m";
+	const END: &str = "\u{1b}[39m";
+
+	#[test]
+	fn guessing_syntax() {
+		assert_eq!(syntax_for("src/main.rs"), Some(&RUST));
+		assert_eq!(syntax_for("a/b.TSX"), Some(&C_LIKE));
+		assert_eq!(syntax_for("Makefile"), Some(&SHELL));
+		assert_eq!(syntax_for("Cargo.toml"), Some(&CONFIG));
+		assert_eq!(syntax_for("README"), None);
+		assert_eq!(syntax_for("notes.txt"), None);
+	}
+
+	#[test]
+	fn highlighting_tokens() {
+		let mut hl = Highlighter::new(&RUST, Depth::Ansi16);
+		assert_eq!(
+			hl.line("let x1 = \"a\\\"b\" + 42; // done"),
+			format!(
+				"{}let{} x1 = {}\"a\\\"b\"{} + {}42{}; {}// done{}",
+				KW, END, STR, END, NUM, END, COMMENT, END
+			)
+		);
+		assert_eq!(hl.line("letter"), "letter");
+	}
+
+	#[test]
+	fn block_comments_span_lines() {
+		let mut hl = Highlighter::new(&C_LIKE, Depth::Ansi16);
+		assert_eq!(
+			hl.line("x /* a"),
+			format!("x {}/*{}{} a{}", COMMENT, END, COMMENT, END)
+		);
+		assert_eq!(
+			hl.line("b */ if"),
+			format!("{}b */{} {}if{}", COMMENT, END, KW, END)
+		);
+	}
+
+	#[test]
+	fn no_highlighting_without_colors() {
+		let mut hl = Highlighter::new(&PYTHON, Depth::None);
+		assert_eq!(hl.line("def f(): # 'x'"), "def f(): # 'x'");
+	}
+}
@@ -0,0 +1,24 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+#[macro_use]
+pub mod path;
+pub mod args;
+pub mod bind;
+pub mod columns;
+pub mod config;
+pub mod export;
+pub mod highlight;
+pub mod preview;
+pub mod sort;
+pub mod style;
+pub mod text;
+pub mod theme;
+pub mod tree;
+pub mod tui;
+pub mod utils;
+
+#[macro_use]
+extern crate log;
@@ -3,23 +3,23 @@
 // All files in the project carrying such notice may not be copied, modified, or
 // distributed except according to those terms.
 
-#[macro_use]
-mod args;
-#[macro_use]
-mod path;
-mod tree;
-mod tui;
-mod utils;
-
 #[macro_use]
 extern crate log;
 
+use fuzzy_tree::bind::Action;
+use fuzzy_tree::export;
+use fuzzy_tree::preview::Previewer;
+use fuzzy_tree::style::{LsColors, Style};
+use fuzzy_tree::theme::Depth;
+use fuzzy_tree::{args, tree, tui, utils};
 use log::Level;
-use std::io;
+use std::io::{self, Read, Write};
 use std::mem;
 use std::process::{self, Command};
-use termion::color;
-use termion::event::Key;
+use termion::event::{Key, MouseButton};
+
+/// Number of lines scrolled by each turn of the mouse wheel.
+const WHEEL_LINES: isize = 3;
 
 fn main() -> Result<(), io::Error> {
 	// env_logger::init();
@@ -27,14 +27,29 @@ fn main() -> Result<(), io::Error> {
 	let cliargs = args::collect();
 	debug!("{:?}", cliargs);
 
-	let stdout = run_cmd(&cliargs.cmd).unwrap_or_else(|e| {
+	if let Some(query) = cliargs.filter.as_ref().or(cliargs.print_tree.as_ref()) {
+		// Candidates can be piped in when filtering, as keys aren't read from stdin
+		let content = if termion::is_tty(&io::stdin()) {
+			get_candidates(&cliargs)
+		} else {
+			let mut content = Vec::new();
+			io::stdin().read_to_end(&mut content)?;
+			content
+		};
+		let matched = run_print(content, query, &cliargs)?;
+		process::exit(if matched { 0 } else { 1 });
+	}
+
+	run_loop(get_candidates(&cliargs), &cliargs)
+}
+
+fn get_candidates(cliargs: &args::Args) -> Vec<u8> {
+	run_cmd(&cliargs.cmd).unwrap_or_else(|e| {
 		utils::exit(&format!(
 			"Failed to execute command `{}`: {}",
-			&cliargs.cmd,
-			e.to_string()
+			&cliargs.cmd, e
 		))
-	});
-	run_loop(stdout, cliargs.n_collapse, cliargs.n_lines)
+	})
 }
 
 fn run_cmd(cmd: &str) -> Result<Vec<u8>, io::Error> {
@@ -45,65 +60,216 @@ fn run_cmd(cmd: &str) -> Result<Vec<u8>, io::Error> {
 	Ok(Command::new(cmd).args(&args).output()?.stdout)
 }
 
-fn run_loop(content: Vec<u8>, n_collapse: usize, n_lines: usize) -> Result<(), io::Error> {
-	let mut tree = tree::Tree::from_stdout(content)?;
-	if n_collapse > 0 {
-		tree.collapse_over(n_collapse)
+/// Create a tree from the candidates in `content`, set up as given by
+/// `cliargs`. Unless `tty`, the tree is drawn without escape sequences.
+fn build_tree(content: Vec<u8>, cliargs: &args::Args, tty: bool) -> Result<tree::Tree, io::Error> {
+	let depth = if tty { Depth::detect() } else { Depth::None };
+	let mut tree = tree::Tree::from_stdout(content, cliargs.follow)?;
+	tree.threads = cliargs.threads;
+	tree.compact_dirs = cliargs.compact_dirs;
+	tree.columns = cliargs.columns;
+	tree.du = cliargs.du;
+	let theme = cliargs.theme.with_depth(depth);
+	tree.style = Style {
+		colors: if theme.has_color() {
+			LsColors::from_env()
+		} else {
+			LsColors::default()
+		},
+		icons: cliargs.icons,
+		theme,
+		guides: cliargs.guides.clone(),
+		plain: !tty,
+	};
+	tree.sort(cliargs.sort_by, cliargs.reverse);
+	Ok(tree)
+}
+
+/// Print the paths which match `query` to stdout, as a tree, one per line or
+/// in the export format. When printing a static tree, directories are
+/// collapsed as they would be interactively. Returns whether anything matched.
+fn run_print(content: Vec<u8>, query: &str, cliargs: &args::Args) -> Result<bool, io::Error> {
+	let mut tree = build_tree(content, cliargs, termion::is_tty(&io::stdout()))?;
+	if cliargs.print_tree.is_some() {
+		if cliargs.n_collapse > 0 {
+			tree.collapse_over(cliargs.n_collapse);
+		}
+		if let Some(depth) = cliargs.max_depth {
+			tree.collapse_below(depth);
+		}
+	}
+	tree.filter(query);
+	// The root and the ancestors of matches are shown, but don't count
+	let matched = !tree.matched_paths().is_empty();
+
+	if let Some(format) = cliargs.export {
+		write_export(&tree, format)?;
+		return Ok(matched);
 	}
-	let lines = tree.as_lines();
-	let prompt = format!("{}> {}", color::Fg(color::Blue), color::Fg(color::Reset));
-	let mut ui = tui::Tui::new(prompt, n_lines, lines.len())?;
-
-	ui.render(tree.info_line(), lines)?;
-
-	for c in tui::iter_keys() {
-		match c? {
-			Key::Esc => break,
-			Key::Char(c) => {
-				if c == '\t' {
-					tree.flip_selected(ui.index());
-					ui.move_down();
-				} else if c == '`' {
-					tree.flip_open(ui.index());
-				} else if c == '\n' {
-					if tree.n_selected == 0 {
+
+	let lines: Vec<String> = if cliargs.flat {
+		tree.matched_paths().iter().map(|p| p.joined()).collect()
+	} else {
+		// Nothing can be selected, so drop the column for the selection marker
+		tree.as_lines().iter().map(|l| l[1..].to_string()).collect()
+	};
+	let stdout = io::stdout();
+	let mut out = stdout.lock();
+	for line in &lines {
+		match writeln!(out, "{}", line) {
+			Err(e) if e.kind() == io::ErrorKind::BrokenPipe => break,
+			result => result?,
+		}
+	}
+
+	Ok(matched)
+}
+
+fn write_export(tree: &tree::Tree, format: export::Format) -> Result<(), io::Error> {
+	let stdout = io::stdout();
+	match export::export(&tree.tree, format, &mut stdout.lock()) {
+		Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+		result => result,
+	}
+}
+
+fn run_loop(content: Vec<u8>, cliargs: &args::Args) -> Result<(), io::Error> {
+	let mut tree = build_tree(content, cliargs, true)?;
+	let theme = tree.style.theme;
+	if cliargs.n_collapse > 0 {
+		tree.collapse_over(cliargs.n_collapse)
+	}
+	let (lines, columns) = tree.as_rows();
+	let prompt = format!("{}> {}", theme.prompt.fg(), theme.prompt.fg_reset());
+	let mut ui = tui::Tui::new(prompt, cliargs.layout, lines.len(), theme)?;
+	if cliargs.mouse {
+		ui.enable_mouse();
+	}
+	// Keys move the cursor the way the tree is drawn
+	let reverse = cliargs.layout.reverse;
+
+	ui.render(tree.info_line(), lines, columns)?;
+
+	let mut keys = tui::KeyReader::spawn();
+	tui::watch_resize(keys.sender())?;
+	let pending = keys.pending();
+	let mut previewer = cliargs
+		.preview
+		.as_ref()
+		.map(|cmd| Previewer::spawn(cmd, theme.depth, keys.sender()));
+	request_preview(&mut previewer, &tree, &ui);
+	// Whether the tree holds the result of a cancelled filter
+	let mut stale = false;
+	let mut accepted = false;
+
+	for event in &mut keys {
+		match event? {
+			tui::Event::Key(Key::Ctrl('c')) => {
+				// Make sure we drop ui so that terminal is reverted from "raw mode"
+				mem::drop(ui);
+				mem::drop(tree);
+				process::exit(130);
+			}
+			tui::Event::Key(key) => {
+				let action = match cliargs.bindings.get(key) {
+					Some(action) => action,
+					None => {
+						match key {
+							Key::Char(c) => ui.insert_char(c),
+							x => debug!("Got {:?}", x),
+						}
+						Action::Ignore
+					}
+				};
+				if stale && matches!(action, Action::Select | Action::ToggleOpen | Action::Accept) {
+					// These index into the displayed tree, so it must be up to date
+					tree.filter(&ui.current_input());
+					stale = false;
+				}
+
+				match action {
+					Action::Abort => break,
+					Action::Accept => {
+						accept(&mut tree, &mut ui, cliargs);
+						accepted = true;
+						break;
+					}
+					Action::Select => {
 						tree.flip_selected(ui.index());
+						ui.move_down();
 					}
-					ui.print_paths(&tree.paths);
+					Action::ToggleOpen => tree.flip_open(ui.index()),
+					Action::ExpandAll => tree.expand_all(),
+					Action::CollapseAll => tree.collapse_below(1),
+					Action::Up if reverse => ui.move_down(),
+					Action::Down if reverse => ui.move_up(),
+					Action::PageUp if reverse => ui.page_down(),
+					Action::PageDown if reverse => ui.page_up(),
+					Action::Up => ui.move_up(),
+					Action::Down => ui.move_down(),
+					Action::PageUp => ui.page_up(),
+					Action::PageDown => ui.page_down(),
+					Action::BackwardChar => ui.move_left(),
+					Action::ForwardChar => ui.move_right(),
+					Action::BeginningOfLine => ui.home(),
+					Action::EndOfLine => ui.end(),
+					Action::BackwardDeleteChar => ui.backspace(),
+					Action::DeleteChar => ui.delete(),
+					Action::UnixLineDiscard => ui.stash(),
+					Action::UnixWordRubout => ui.word_stash(),
+					Action::Yank => ui.pop(),
+					Action::CycleSort => tree.cycle_sort(),
+					Action::ToggleColumns => tree.columns.toggle(),
+					Action::TogglePreview => ui.toggle_preview(),
+					Action::ScrollLeft => ui.scroll_left(),
+					Action::ScrollRight => ui.scroll_right(),
+					Action::PreviewUp => ui.scroll_preview(-1),
+					Action::PreviewDown => ui.scroll_preview(1),
+					Action::PreviewHalfPageUp => ui.scroll_preview(-ui.preview_half()),
+					Action::PreviewHalfPageDown => ui.scroll_preview(ui.preview_half()),
+					Action::Ignore => {}
+				}
+			}
+			tui::Event::Mouse(mouse) => {
+				if stale {
+					tree.filter(&ui.current_input());
+					stale = false;
+				}
+				if on_mouse(mouse, &mut tree, &mut ui, reverse) {
+					accept(&mut tree, &mut ui, cliargs);
+					accepted = true;
 					break;
-				} else {
-					ui.insert_char(c);
 				}
 			}
-			Key::Ctrl(c) => {
-				match c {
-					'c' => {
-						// Make sure we drop ui so that terminal is reverted from "raw mode"
-						mem::drop(ui);
-						mem::drop(tree);
-						process::exit(130);
-					}
-					'u' => ui.stash(),
-					'w' => ui.word_stash(),
-					'y' => ui.pop(),
-					x => debug!("Got ctrl-{}", x),
+			tui::Event::Preview => {
+				if let Some(lines) = previewer.as_ref().and_then(Previewer::take) {
+					ui.set_preview(lines);
+					ui.render_preview()?;
 				}
+				continue;
+			}
+			tui::Event::Resize => {
+				ui.resize()?;
+				let (lines, columns) = tree.as_rows();
+				ui.render(tree.info_line(), lines, columns)?;
+				continue;
 			}
-			Key::Left => ui.move_left(),
-			Key::Right => ui.move_right(),
-			Key::Up => ui.move_up(),
-			Key::Down => ui.move_down(),
-			Key::PageUp => ui.page_up(),
-			Key::PageDown => ui.page_down(),
-			Key::Backspace => ui.backspace(),
-			Key::Delete => ui.delete(),
-			Key::Home => ui.home(),
-			Key::End => ui.end(),
-			x => debug!("Got {:?}", x),
 		}
 
-		if ui.chars_changed {
-			tree.filter(&ui.current_input());
+		if pending.any() {
+			// Newer input is waiting, only the prompt is worth redrawing. The
+			// tree still holds the old query until it is filtered again
+			stale |= ui.chars_changed;
+			ui.render_input()?;
+			continue;
+		}
+
+		if ui.chars_changed || stale {
+			stale = !tree.filter_until(&ui.current_input(), &|| pending.any());
+			if stale {
+				ui.render_input()?;
+				continue;
+			}
 		}
 
 		let mut info_line = tree.info_line();
@@ -111,10 +277,82 @@ fn run_loop(content: Vec<u8>, n_collapse: usize, n_lines: usize) -> Result<(), i
 			info_line += &ui.info_line();
 		}
 
-		ui.render(info_line, tree.as_lines())?;
+		let (lines, columns) = tree.as_rows();
+		ui.render(info_line, lines, columns)?;
+		request_preview(&mut previewer, &tree, &ui);
 	}
 
 	ui.flush()?;
 
+	if let (true, Some(format)) = (accepted, cliargs.export) {
+		// The terminal must be out of raw mode before writing multiple lines
+		mem::drop(ui);
+		write_export(&tree, format)?;
+	}
+
 	Ok(())
 }
+
+/// Select the path under the cursor if nothing else is, and leave the
+/// interface showing the selection, unless it is to be exported.
+fn accept(tree: &mut tree::Tree, ui: &mut tui::Tui, cliargs: &args::Args) {
+	if tree.n_selected == 0 {
+		tree.flip_selected(ui.index());
+	}
+	if cliargs.export.is_some() {
+		ui.clear();
+	} else {
+		ui.print_paths(&tree.paths);
+	}
+}
+
+/// Handle a press of a mouse button. Clicking a line moves the cursor to it,
+/// or opens or closes it if on a directory's marker, and with a modifier held
+/// selects it. Returns whether the selection was accepted by a double click.
+fn on_mouse(mouse: tui::Mouse, tree: &mut tree::Tree, ui: &mut tui::Tui, reverse: bool) -> bool {
+	match mouse.button {
+		MouseButton::WheelUp | MouseButton::WheelDown => {
+			let up = mouse.button == MouseButton::WheelUp;
+			if ui.over_preview(mouse.x, mouse.y) {
+				ui.scroll_preview(if up { -WHEEL_LINES } else { WHEEL_LINES });
+			} else {
+				// Lines further up the screen are later in a reversed tree
+				ui.scroll(if up == reverse {
+					WHEEL_LINES
+				} else {
+					-WHEEL_LINES
+				});
+			}
+			false
+		}
+		MouseButton::Left => {
+			let (index, col) = match ui.line_at(mouse.x, mouse.y) {
+				Some(position) => position,
+				None => return false,
+			};
+			let double = ui.click(index);
+			if mouse.modified {
+				tree.flip_selected(index);
+				false
+			} else if tree
+				.marker_columns(index)
+				.is_some_and(|(start, end)| (start..end).contains(&col))
+			{
+				tree.flip_open(index);
+				false
+			} else {
+				double
+			}
+		}
+		_ => false,
+	}
+}
+
+/// Preview the path under the cursor, if the preview is shown.
+fn request_preview(previewer: &mut Option<Previewer>, tree: &tree::Tree, ui: &tui::Tui) {
+	if let (Some(previewer), true) = (previewer, ui.preview_shown()) {
+		if let Some(pth) = tree.ith(ui.index()) {
+			previewer.request(pth);
+		}
+	}
+}
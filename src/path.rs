@@ -3,27 +3,114 @@
 // All files in the project carrying such notice may not be copied, modified, or
 // distributed except according to those terms.
 
-use std::cell::RefCell;
-use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::fmt;
 use std::fs;
 use std::io::{self, ErrorKind};
+use std::os::unix::fs::MetadataExt;
 use std::path;
-use std::rc::Rc;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::SystemTime;
+
+/// A shared handle to a `Path`. Handles are `Send` and `Sync` so that paths
+/// can be matched from multiple threads.
+#[derive(Clone)]
+pub struct ArcPath(Arc<RwLock<Path>>);
+
+/// The target of a symbolic link.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Link {
+	pub target: Arc<str>,
+	/// Whether the target does not exist
+	pub broken: bool,
+}
+
+/// Filesystem metadata recorded for each path.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Meta {
+	pub is_dir: bool,
+	pub size: u64,
+	pub modified: Option<SystemTime>,
+	/// Unix file type and permission bits
+	pub mode: u32,
+	pub uid: u32,
+	/// Set if the path is a symbolic link
+	pub link: Option<Link>,
+}
+
+impl Meta {
+	/// Read the metadata of the file at `name`. A symbolic link is described by
+	/// its own metadata, unless `follow` and it is not broken, in which case its
+	/// target's metadata is used. The link's target is recorded either way.
+	pub fn read(name: &str, follow: bool) -> Meta {
+		let meta = match fs::symlink_metadata(name) {
+			Ok(meta) => meta,
+			Err(_) => return Meta::default(),
+		};
+		if !meta.file_type().is_symlink() {
+			return Meta::from(&meta);
+		}
 
-pub type RcPath = Rc<RefCell<Path>>;
+		let target = fs::read_link(name).map_or_else(|_| "".into(), |t| t.to_string_lossy().into());
+		let resolved = fs::metadata(name);
+		let mut read = match &resolved {
+			Ok(resolved) if follow => Meta::from(resolved),
+			_ => Meta::from(&meta),
+		};
+		read.link = Some(Link {
+			target,
+			broken: resolved.is_err(),
+		});
+		read
+	}
+}
+
+impl From<&fs::Metadata> for Meta {
+	fn from(m: &fs::Metadata) -> Self {
+		Meta {
+			is_dir: m.is_dir(),
+			size: m.len(),
+			modified: m.modified().ok(),
+			mode: m.mode(),
+			uid: m.uid(),
+			link: None,
+		}
+	}
+}
 
+/// Metadata for a path which is only known to be a directory or not.
+impl From<bool> for Meta {
+	fn from(is_dir: bool) -> Self {
+		Meta {
+			is_dir,
+			..Meta::default()
+		}
+	}
+}
+
+/// A node in the directory tree. Only the node's own name is stored, its full
+/// path is reconstructed from its ancestors on demand.
 #[derive(Eq, PartialEq)]
 pub struct Path {
-	pub components: Vec<String>,
-	pub parent: Option<RcPath>,
-	pub children: Option<Vec<RcPath>>,
+	pub name: Arc<str>,
+	pub parent: Option<ArcPath>,
+	pub children: Option<Vec<ArcPath>>,
 	pub is_dir: bool,
+	pub size: u64,
+	pub modified: Option<SystemTime>,
+	pub mode: u32,
+	pub uid: u32,
+	/// Size of a file, or the total size of all files below a directory
+	pub total_size: u64,
+	pub link: Option<Link>,
 	pub open: bool,
 	pub matched: bool,
-	pub match_text: String,
+	/// The name with matches highlighted, `None` if it should be shown as is
+	pub match_text: Option<String>,
 	pub selected: bool,
-	pub joined: String,
+	/// Set on the root if all names below it were given starting with `./`,
+	/// which their full paths keep
+	pub dot_prefix: bool,
 }
 
 impl fmt::Debug for Path {
@@ -31,115 +118,148 @@ impl fmt::Debug for Path {
 		write!(
 			f,
 			"{:?}; Selected: {}; Matched {}; Children {:#?}:",
-			self.components, self.selected, self.matched, self.children
+			self.name, self.selected, self.matched, self.children
 		)
 	}
 }
 
-impl Ord for Path {
-	fn cmp(&self, other: &Self) -> Ordering {
-		return self.joined.cmp(&other.joined);
-	}
-}
-
-impl PartialOrd for Path {
-	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-		Some(self.cmp(other))
-	}
-}
-
 impl Path {
-	pub fn new(pathname: String, is_dir: bool) -> RcPath {
-		let components: Vec<String> = pathname
-			.split(path::MAIN_SEPARATOR)
-			.map(|x| x.to_string())
-			.collect();
-		let match_text = components[components.len() - 1].clone();
-
-		Rc::new(RefCell::new(Path {
+	#[allow(clippy::new_ret_no_self)]
+	pub fn new(name: Arc<str>, meta: Meta) -> ArcPath {
+		ArcPath(Arc::new(RwLock::new(Path {
+			name,
 			parent: None,
-			components,
-			joined: pathname,
+			children: None,
 			selected: false,
 			matched: true,
-			match_text,
-			is_dir,
+			match_text: None,
+			is_dir: meta.is_dir,
+			size: meta.size,
+			modified: meta.modified,
+			mode: meta.mode,
+			uid: meta.uid,
+			total_size: meta.size,
+			link: meta.link,
 			open: true,
-			children: None,
-		}))
+			dot_prefix: false,
+		})))
 	}
+}
 
-	pub fn from(pathname: &str, is_dir: bool) -> RcPath {
-		Path::new(pathname.to_string(), is_dir)
-	}
+fn add(child: &ArcPath, parent: &ArcPath) {
+	parent
+		.borrow_mut()
+		.children
+		.get_or_insert_with(Vec::new)
+		.push(child.clone());
+	child.borrow_mut().parent = Some(parent.clone());
 }
 
-fn add(child: &RcPath, parent: &RcPath) {
-	let mut children = match parent.borrow_mut().children.take() {
-		Some(v) => v,
-		None => Vec::new(),
-	};
-	children.push(Rc::clone(child));
-	parent.borrow_mut().children = Some(children);
-	child.borrow_mut().parent = Some(Rc::clone(parent));
+impl PartialEq for ArcPath {
+	fn eq(&self, other: &Self) -> bool {
+		self.ptr_eq(other) || *self.borrow() == *other.borrow()
+	}
 }
 
-// Since `Path`s are wrapped in `Rc`s which cannot be implemented on directly,
-// functionality is implemented via a trait.
-pub trait PathBehaviour {
-	fn flip_open(&self);
-	fn add_child(&self, child: &RcPath);
-	fn add_parent(&self, parent: &RcPath);
-	fn is_child_of(&self, other: &RcPath) -> bool;
-	fn basename(&self) -> &str;
-	fn len(&self) -> usize;
-	fn n_children(&self) -> usize;
-	fn n_descendants(&self) -> usize;
+impl Eq for ArcPath {}
+
+impl fmt::Debug for ArcPath {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.borrow().fmt(f)
+	}
 }
 
-impl PathBehaviour for RcPath {
-	// TODO: Also add helper methods for borrow of `matched`, `selected`, `children`
-	// etc.. This could be done with a macro?
+impl ArcPath {
+	/// Lock the path for reading. Panics if a writer panicked while holding the
+	/// lock.
+	pub fn borrow(&self) -> RwLockReadGuard<'_, Path> {
+		self.0.read().unwrap()
+	}
 
-	fn flip_open(&self) {
+	/// Lock the path for writing. Panics if a writer panicked while holding the
+	/// lock.
+	pub fn borrow_mut(&self) -> RwLockWriteGuard<'_, Path> {
+		self.0.write().unwrap()
+	}
+
+	/// Whether both handles refer to the same path.
+	pub fn ptr_eq(&self, other: &ArcPath) -> bool {
+		Arc::ptr_eq(&self.0, &other.0)
+	}
+
+	pub fn flip_open(&self) {
 		let mut p = self.borrow_mut();
 		p.open = !p.open;
 	}
 
-	fn add_child(&self, child: &RcPath) {
+	pub fn add_child(&self, child: &ArcPath) {
 		add(child, self);
 	}
 
-	fn add_parent(&self, parent: &RcPath) {
-		add(self, parent);
+	pub fn is_root(&self) -> bool {
+		self.borrow().parent.is_none()
 	}
 
-	fn is_child_of(&self, other: &RcPath) -> bool {
-		if other.len() >= self.len() {
-			return false;
-		}
-		self.borrow().components[..other.len()] == other.borrow().components[..]
+	pub fn basename(&self) -> Arc<str> {
+		Arc::clone(&self.borrow().name)
 	}
 
-	fn basename(&self) -> &str {
-		// TODO: See if there is a safe way around this:
-		unsafe { &(*self.as_ptr()).components[self.len() - 1] }
+	/// Number of components in the path, including the root.
+	pub fn depth(&self) -> usize {
+		let mut depth = 1;
+		let mut parent = self.borrow().parent.clone();
+		while let Some(pth) = parent {
+			depth += 1;
+			parent = pth.borrow().parent.clone();
+		}
+		depth
 	}
 
-	fn len(&self) -> usize {
-		self.borrow().components.len()
+	/// The full path relative to the root, as it was given: absolute paths
+	/// start with `/`, and `./` is kept if every name had it. The root itself
+	/// is `.`.
+	pub fn joined(&self) -> String {
+		let mut names = Vec::new();
+		let mut node = self.clone();
+		loop {
+			let parent = node.borrow().parent.clone();
+			match parent {
+				Some(parent) => {
+					names.push(node.basename());
+					node = parent;
+				}
+				None if names.is_empty() => return node.basename().to_string(),
+				None => break,
+			}
+		}
+		names.reverse();
+
+		let mut joined =
+			String::with_capacity(names.iter().map(|n| n.len() + 1).sum::<usize>() + 2);
+		if !is_fs_root(&names[0]) {
+			joined.push_str(node.prefix());
+		}
+		for (i, name) in names.iter().enumerate() {
+			if i > 0 && !is_fs_root(&names[i - 1]) {
+				joined.push(path::MAIN_SEPARATOR);
+			}
+			joined.push_str(name);
+		}
+		joined
 	}
 
-	fn n_children(&self) -> usize {
-		if let Some(children) = &self.borrow().children {
-			children.len()
+	/// What the full paths below a root start with, `./` if every name was
+	/// given with it.
+	pub fn prefix(&self) -> &'static str {
+		if self.borrow().dot_prefix {
+			"./"
 		} else {
-			0
+			""
 		}
 	}
 
 	/// Total number of descendants of a path
-	fn n_descendants(&self) -> usize {
+	pub fn n_descendants(&self) -> usize {
 		let mut i = 0;
 		if let Some(children) = &self.borrow().children {
 			for child in children.iter() {
@@ -150,34 +270,135 @@ impl PathBehaviour for RcPath {
 	}
 }
 
-/// Create multiple paths from a `find`-like command output.
-pub fn create_paths(string: Vec<u8>) -> Result<Vec<RcPath>, io::Error> {
-	let mut paths: Vec<RcPath> = String::from_utf8(string)
-		.map_err(|e| {
-			io::Error::new(
-				ErrorKind::InvalidInput,
-				format!("error reading stdout: {}", e),
-			)
-		})?
-		.split('\n')
-		.filter(|x| !x.is_empty())
-		.map(|x| Path::from(x, fs::metadata(&x).map_or_else(|_| false, |v| v.is_dir())))
-		.collect();
+/// Interns path component names so that names which are repeated across the
+/// tree (`src`, `mod.rs`, `README.md`...) share a single allocation.
+#[derive(Default)]
+struct Interner(HashSet<Arc<str>>);
+
+impl Interner {
+	fn intern(&mut self, name: &str) -> Arc<str> {
+		if let Some(interned) = self.0.get(name) {
+			return Arc::clone(interned);
+		}
+		let interned: Arc<str> = Arc::from(name);
+		self.0.insert(Arc::clone(&interned));
+		interned
+	}
+}
+
+/// The components of a path name, ignoring any `.` components. An absolute
+/// name starts with a component for the filesystem root.
+fn components(name: &str) -> impl Iterator<Item = &str> {
+	let fs_root = if name.starts_with(path::MAIN_SEPARATOR) {
+		Some(&name[..path::MAIN_SEPARATOR.len_utf8()])
+	} else {
+		None
+	};
+	fs_root.into_iter().chain(
+		name.split(path::MAIN_SEPARATOR)
+			.filter(|c| !c.is_empty() && *c != "."),
+	)
+}
 
-	paths.sort();
+/// Whether a component is the filesystem root, which is followed by no
+/// separator when joined.
+fn is_fs_root(name: &str) -> bool {
+	let mut chars = name.chars();
+	chars.next() == Some(path::MAIN_SEPARATOR) && chars.next().is_none()
+}
 
-	for p in &mut paths {
-		if p.borrow().components[0] != "." {
-			p.borrow_mut().components.insert(0, ".".to_string());
+fn is_ancestor(ancestor: &[&str], comps: &[&str]) -> bool {
+	ancestor.len() < comps.len() && ancestor[..] == comps[..ancestor.len()]
+}
+
+/// Create linked paths from `(name, metadata)` pairs, rooted at a `.`
+/// directory. Metadata can be given as just whether the path is a directory.
+///
+/// Names are sorted component by component, so that the returned paths are in
+/// the same order as they are displayed. Directories which are missing from
+/// `names` are created, so every path is linked to its true parent.
+pub fn build_paths<S: AsRef<str>, M: Into<Meta>>(names: Vec<(S, M)>) -> Vec<ArcPath> {
+	let mut names: Vec<(S, Meta)> = names.into_iter().map(|(s, m)| (s, m.into())).collect();
+	names.sort_by(|a, b| components(a.0.as_ref()).cmp(components(b.0.as_ref())));
+	names.dedup_by(|a, b| components(a.0.as_ref()).eq(components(b.0.as_ref())));
+
+	let mut interner = Interner::default();
+	let root = Path::new(interner.intern("."), Meta::from(true));
+	// Names listed by e.g. `find .` all start with `./`, the root aside
+	let mut below_root = names
+		.iter()
+		.map(|n| n.0.as_ref())
+		.filter(|n| components(n).next().is_some())
+		.peekable();
+	root.borrow_mut().dot_prefix =
+		below_root.peek().is_some() && below_root.all(|n| n.starts_with("./"));
+	let mut paths = vec![root.clone()];
+	// The ancestors of the previous path, along with their components
+	let mut stack: Vec<(ArcPath, Vec<&str>)> = vec![(root, Vec::new())];
+
+	for (name, meta) in &names {
+		let comps: Vec<&str> = components(name.as_ref()).collect();
+		if comps.is_empty() {
+			continue; // The root is always present
+		}
+
+		while !is_ancestor(&stack[stack.len() - 1].1, &comps) {
+			stack.pop();
+		}
+
+		for depth in stack[stack.len() - 1].1.len()..comps.len() {
+			let meta = if depth < comps.len() - 1 {
+				Meta::from(true)
+			} else {
+				meta.clone()
+			};
+			let pth = Path::new(interner.intern(comps[depth]), meta);
+			stack[stack.len() - 1].0.add_child(&pth);
+			paths.push(pth.clone());
+			stack.push((pth, comps[..=depth].to_vec()));
 		}
 	}
 
-	if paths[0].borrow().joined != "." {
-		// Add CWD as "." if not present
-		paths.insert(0, Path::from(".", true));
+	aggregate_sizes(&paths);
+	paths
+}
+
+/// Set the `total_size` of each directory to the sum of the sizes of all
+/// matched files below it. `paths` must be in display order, so that every
+/// path comes after its parent.
+pub fn aggregate_sizes(paths: &[ArcPath]) {
+	for path in paths.iter().filter(|p| p.borrow().is_dir) {
+		path.borrow_mut().total_size = 0;
+	}
+	for path in paths.iter().rev() {
+		let pth = path.borrow();
+		if !pth.matched {
+			continue;
+		}
+		if let Some(parent) = &pth.parent {
+			let size = if pth.is_dir { pth.total_size } else { pth.size };
+			parent.borrow_mut().total_size += size;
+		}
 	}
+}
+
+/// Create multiple paths from a `find`-like command output. If `follow`,
+/// symbolic links are described by their targets, so that linked directories
+/// are treated as directories.
+pub fn create_paths(string: Vec<u8>, follow: bool) -> Result<Vec<ArcPath>, io::Error> {
+	let string = String::from_utf8(string).map_err(|e| {
+		io::Error::new(
+			ErrorKind::InvalidInput,
+			format!("error reading stdout: {}", e),
+		)
+	})?;
+	let names = string
+		.split('\n')
+		.filter(|x| !x.is_empty())
+		.map(|x| (x, Meta::read(x, follow)))
+		.collect();
 
-	Ok(paths)
+	Ok(build_paths(names))
 }
 
 #[macro_export]
@@ -186,10 +407,10 @@ macro_rules! paths {
 		{
 			let mut temp = Vec::new();
 			$(
-				let is_dir = $x.matches('.').collect::<Vec<_>>().len() == 1;
-				temp.push(Path::from($x, is_dir));
+				let is_dir = $x.matches('.').count() == 1;
+				temp.push(($x, is_dir));
 			)*
-			temp
+			$crate::path::build_paths(temp)
 		}
 	};
 }
@@ -198,55 +419,131 @@ macro_rules! paths {
 mod test {
 	use super::*;
 
+	fn joined(paths: &[ArcPath]) -> Vec<String> {
+		paths.iter().map(|p| p.joined()).collect()
+	}
+
 	#[test]
 	fn sorting_is_correct() {
-		let mut path1;
-		let mut path2;
-
-		path1 = Path::from("here/is/a/path.c", false);
-		path2 = Path::from("here/is/a/path.c", false);
-		assert_eq!(path1, path2);
+		let paths = paths!["src", "tmp", "src/main.rs"];
+		assert_eq!(joined(&paths), vec![".", "src", "src/main.rs", "tmp"]);
 
-		path1 = Path::from("here/is/a", false);
-		path2 = Path::from("here/is/a/path.c", false);
-		assert!(path1 < path2);
+		// Children are sorted before siblings which share a prefix
+		let paths = paths!["a-b", "a/c", "a"];
+		assert_eq!(joined(&paths), vec![".", "a", "a/c", "a-b"]);
+	}
 
-		path1 = Path::from("here/is/a/fath.c", false);
-		path2 = Path::from("here/is/a/path.c", false);
-		assert!(path1 < path2);
+	#[test]
+	fn leading_dots_are_ignored() {
+		let paths = paths![".", "./src", "./src/main.rs", "src/lib.rs"];
+		assert_eq!(
+			joined(&paths),
+			vec![".", "src", "src/lib.rs", "src/main.rs"]
+		);
+	}
 
-		let mut paths = paths!["src", "tmp", "src/main.rs"];
-		paths.sort();
-		let expected = paths!["src", "src/main.rs", "tmp"];
-		assert_eq!(paths, expected);
+	#[test]
+	fn prefixes_are_kept() {
+		let paths = paths![".", "./src", "./src/main.rs"];
+		assert_eq!(joined(&paths), vec![".", "./src", "./src/main.rs"]);
+
+		let paths = build_paths(vec![
+			("/usr/bin", true),
+			("/usr/bin/env", false),
+			("/tmp", true),
+		]);
+		assert_eq!(
+			joined(&paths),
+			vec![".", "/", "/tmp", "/usr", "/usr/bin", "/usr/bin/env"]
+		);
+
+		// Relative names aren't moved below an absolute one's directories
+		let paths = build_paths(vec![("/a/b", false), ("a/c", false)]);
+		assert_eq!(joined(&paths), vec![".", "/", "/a", "/a/b", "a", "a/c"]);
 	}
 
 	#[test]
-	fn len_correct() {
-		let s = "here/is/a/path.c";
-		let path = Path::from(s, false);
-		assert_eq!(path.len(), 4);
+	fn depth_correct() {
+		let paths = paths!["here/is/a/path.c"];
+		assert_eq!(paths[4].depth(), 5);
+		assert_eq!(paths[0].depth(), 1);
 	}
 
 	#[test]
 	fn basename_correct() {
-		let s = "here/is/a/path.c";
-		let path = Path::from(s, false);
-		assert_eq!(path.basename(), "path.c");
+		let paths = paths!["here/is/a/path.c"];
+		assert_eq!(&*paths[4].basename(), "path.c");
+	}
+
+	#[test]
+	fn missing_directories_are_created() {
+		let paths = build_paths(vec![("src/bayes/blend.c", false), ("src/cakes/a.c", false)]);
+		assert_eq!(
+			joined(&paths),
+			vec![
+				".",
+				"src",
+				"src/bayes",
+				"src/bayes/blend.c",
+				"src/cakes",
+				"src/cakes/a.c"
+			]
+		);
+		assert!(paths[2].borrow().is_dir);
+		assert!(!paths[3].borrow().is_dir);
+		assert!(paths[3].borrow().parent.as_ref().unwrap().ptr_eq(&paths[2]));
+		assert!(paths[5].borrow().parent.as_ref().unwrap().ptr_eq(&paths[4]));
+	}
+
+	#[test]
+	fn directory_sizes_are_aggregated() {
+		let file = |size| Meta {
+			size,
+			..Meta::default()
+		};
+		let paths = build_paths(vec![("a/b/c", file(1)), ("a/d", file(2)), ("e", file(4))]);
+		let totals: Vec<u64> = paths.iter().map(|p| p.borrow().total_size).collect();
+		assert_eq!(joined(&paths), vec![".", "a", "a/b", "a/b/c", "a/d", "e"]);
+		assert_eq!(totals, vec![7, 3, 1, 1, 2, 4]);
+	}
+
+	#[test]
+	fn symlinks_are_recorded() {
+		let dir = tempfile::tempdir().unwrap();
+		let name = |n: &str| dir.path().join(n).to_string_lossy().into_owned();
+		fs::create_dir(name("real")).unwrap();
+		std::os::unix::fs::symlink("real", name("linked")).unwrap();
+		std::os::unix::fs::symlink("missing", name("broken")).unwrap();
+
+		let real = Meta::read(&name("real"), false);
+		assert!(real.is_dir && real.link.is_none());
+
+		let linked = Meta::read(&name("linked"), false);
+		let link = Some(Link {
+			target: "real".into(),
+			broken: false,
+		});
+		assert!(!linked.is_dir);
+		assert_eq!(linked.link, link);
+
+		let linked = Meta::read(&name("linked"), true);
+		assert!(linked.is_dir);
+		assert_eq!(linked.link, link);
+
+		let broken = Meta::read(&name("broken"), true);
+		assert!(!broken.is_dir);
+		assert_eq!(
+			broken.link,
+			Some(Link {
+				target: "missing".into(),
+				broken: true
+			})
+		);
 	}
 
 	#[test]
-	fn is_child_of_correctness() {
-		let mut p1 = Path::from("A", false);
-		let mut p2 = Path::from("B", false);
-		assert!(!p1.is_child_of(&p2));
-
-		p1 = Path::from("src/bayes", true);
-		p2 = Path::from("src/bayes/blend.c", false);
-		assert!(!p1.is_child_of(&p2));
-
-		p1 = Path::from("src/bayes", true);
-		p2 = Path::from("src/bayes/blend.c", false);
-		assert!(p2.is_child_of(&p1));
+	fn names_are_interned() {
+		let paths = paths!["a/mod.rs", "b/mod.rs"];
+		assert!(Arc::ptr_eq(&paths[2].basename(), &paths[4].basename()));
 	}
 }
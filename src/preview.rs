@@ -0,0 +1,392 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+use crate::columns::{human_size, MTIME_FORMAT};
+use crate::highlight::{self, Highlighter};
+use crate::path::ArcPath;
+use crate::theme::{Color, Depth};
+use crate::tui::{Event, EventSender};
+use chrono::{DateTime, Local};
+use std::fs;
+use std::io::{self, Read};
+use std::iter;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Maximum number of bytes read for a preview.
+const MAX_BYTES: u64 = 256 * 1024;
+/// Maximum number of bytes of a file read by the built-in preview.
+const FILE_BYTES: u64 = 64 * 1024;
+/// Number of bytes checked when deciding whether a file is binary.
+const BINARY_CHECK: usize = 8 * 1024;
+/// Maximum number of lines of a preview which are kept for scrolling.
+const MAX_LINES: usize = 1000;
+/// Longest a preview command may run before it is killed.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// How often a running command checks whether it has been superseded.
+const POLL: Duration = Duration::from_millis(20);
+
+/// Where the preview pane is drawn relative to the tree.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Position {
+	Right,
+	Down,
+}
+
+impl FromStr for Position {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"right" => Ok(Position::Right),
+			"down" => Ok(Position::Down),
+			_ => Err(format!("unknown preview position: {}", s)),
+		}
+	}
+}
+
+struct Request {
+	path: String,
+	is_dir: bool,
+}
+
+/// Creates previews on a background thread, so that slow commands never
+/// block input. Only the newest request is previewed when several are
+/// waiting, and a command still running when a newer request arrives is
+/// killed.
+pub struct Previewer {
+	tx: mpsc::Sender<Request>,
+	latest: Arc<Mutex<Option<Vec<String>>>>,
+	requested: Option<String>,
+}
+
+impl Previewer {
+	/// Start previewing with `cmd`, in which `{}` is replaced by the quoted
+	/// path. An empty `cmd` uses the built-in preview, which is coloured for a
+	/// terminal of `depth`. `Event::Preview` is sent on `events` whenever a
+	/// preview is ready to be taken.
+	pub fn spawn(cmd: &str, depth: Depth, events: EventSender) -> Self {
+		let (tx, rx) = mpsc::channel::<Request>();
+		let latest = Arc::new(Mutex::new(None));
+		let done = Arc::clone(&latest);
+		let cmd = cmd.to_string();
+
+		thread::spawn(move || {
+			let mut next = None;
+			while let Some(mut request) = next.take().or_else(|| rx.recv().ok()) {
+				// The cursor has already moved past any older requests
+				while let Ok(newer) = rx.try_recv() {
+					request = newer;
+				}
+				let lines = if cmd.is_empty() {
+					builtin(&request.path, request.is_dir, depth)
+				} else {
+					let mut superseded = || {
+						if let Ok(newer) = rx.try_recv() {
+							next = Some(newer);
+						}
+						next.is_some()
+					};
+					match run(&cmd, &request.path, TIMEOUT, &mut superseded) {
+						Some(lines) => lines,
+						None => continue,
+					}
+				};
+				*done.lock().unwrap() = Some(lines);
+				if events.send(Ok(Event::Preview)).is_err() {
+					break;
+				}
+			}
+		});
+
+		Previewer {
+			tx,
+			latest,
+			requested: None,
+		}
+	}
+
+	/// Preview `pth`, unless it was the last path requested.
+	pub fn request(&mut self, pth: &ArcPath) {
+		let path = pth.joined();
+		if self.requested.as_ref() == Some(&path) {
+			return;
+		}
+		let is_dir = pth.borrow().is_dir;
+		self.requested = Some(path.clone());
+		// The thread only stops if the receiver of events has gone
+		let _ = self.tx.send(Request { path, is_dir });
+	}
+
+	/// Take the most recently finished preview, if it hasn't been taken yet.
+	pub fn take(&self) -> Option<Vec<String>> {
+		self.latest.lock().unwrap().take()
+	}
+}
+
+/// Run `cmd` for `path`, returning its output, including stderr. The command
+/// is killed if it runs for longer than `timeout`, or if `superseded` becomes
+/// true, in which case `None` is returned.
+fn run(
+	cmd: &str,
+	path: &str,
+	timeout: Duration,
+	superseded: &mut dyn FnMut() -> bool,
+) -> Option<Vec<String>> {
+	let cmd = cmd.replace("{}", &shell_quote(path));
+	let child = Command::new("sh")
+		.arg("-c")
+		.arg(format!("{{ {}\n}} 2>&1", cmd))
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		// In its own group, so that everything it starts can be killed with it
+		.process_group(0)
+		.spawn();
+	let mut child = match child {
+		Ok(child) => child,
+		Err(e) => return Some(vec![format!("Failed to execute command `{}`: {}", cmd, e)]),
+	};
+
+	// Output is read on its own thread, so that a command which hangs can be
+	// given up on. The thread finishes once the output is closed
+	let stdout = child.stdout.take();
+	let (tx, rx) = mpsc::channel();
+	thread::spawn(move || {
+		let mut output = Vec::new();
+		if let Some(stdout) = stdout {
+			let _ = stdout.take(MAX_BYTES).read_to_end(&mut output);
+		}
+		let _ = tx.send(output);
+	});
+
+	let deadline = Instant::now() + timeout;
+	let lines = loop {
+		match rx.recv_timeout(POLL) {
+			Ok(output) => break Some(to_lines(&output)),
+			Err(RecvTimeoutError::Disconnected) => break Some(Vec::new()),
+			Err(RecvTimeoutError::Timeout) if superseded() => break None,
+			Err(RecvTimeoutError::Timeout) if Instant::now() >= deadline => {
+				break Some(vec![format!(
+					"Command `{}` timed out after {}s",
+					cmd,
+					timeout.as_secs_f32()
+				)]);
+			}
+			Err(RecvTimeoutError::Timeout) => {}
+		}
+	};
+	// The command may still be running if its output was cut short. `sh` runs
+	// it as a child of its own, so the whole group is killed
+	unsafe {
+		libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+	}
+	let _ = child.wait();
+	lines
+}
+
+fn shell_quote(s: &str) -> String {
+	format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+fn to_lines(bytes: &[u8]) -> Vec<String> {
+	String::from_utf8_lossy(bytes)
+		.lines()
+		.take(MAX_LINES)
+		.map(String::from)
+		.collect()
+}
+
+/// Preview a file by its first lines, numbered and highlighted, or a
+/// directory by the names of its children. Either is headed by its size and
+/// modification time.
+fn builtin(path: &str, is_dir: bool, depth: Depth) -> Vec<String> {
+	let dim = Color::Basic(8).with_depth(depth);
+	let lines = if is_dir {
+		list_dir(path)
+	} else {
+		head(path, depth, dim)
+	};
+
+	match (fs::metadata(path), lines) {
+		(Ok(meta), Ok(lines)) => {
+			let size = if is_dir {
+				format!("{} entries", lines.len())
+			} else {
+				human_size(meta.len())
+			};
+			let mtime = meta.modified().ok().map_or_else(String::new, |t| {
+				DateTime::<Local>::from(t).format(MTIME_FORMAT).to_string()
+			});
+			let header = format!("{}{}  {}{}", dim.fg(), size, mtime, dim.fg_reset());
+			iter::once(header).chain(lines).collect()
+		}
+		(Err(e), _) | (_, Err(e)) => vec![format!("Cannot preview {}: {}", path, e)],
+	}
+}
+
+fn head(path: &str, depth: Depth, dim: Color) -> Result<Vec<String>, io::Error> {
+	let mut bytes = Vec::new();
+	fs::File::open(path)?
+		.take(FILE_BYTES)
+		.read_to_end(&mut bytes)?;
+	if is_binary(&bytes) {
+		return Ok(vec!["Binary file, not shown".to_string()]);
+	}
+
+	let mut highlighter = highlight::syntax_for(path).map(|s| Highlighter::new(s, depth));
+	Ok(to_lines(&bytes)
+		.iter()
+		.enumerate()
+		.map(|(i, line)| {
+			let line = match &mut highlighter {
+				Some(highlighter) => highlighter.line(line),
+				None => line.to_string(),
+			};
+			format!("{}{:>4}{} {}", dim.fg(), i + 1, dim.fg_reset(), line)
+		})
+		.collect())
+}
+
+/// Whether the start of a file looks like binary rather than text, i.e. it
+/// contains a NUL or isn't UTF-8.
+fn is_binary(bytes: &[u8]) -> bool {
+	let start = &bytes[..bytes.len().min(BINARY_CHECK)];
+	if start.contains(&0) {
+		return true;
+	}
+	match std::str::from_utf8(start) {
+		Ok(_) => false,
+		// A character may have been cut off at the end of the check
+		Err(e) => e.error_len().is_some(),
+	}
+}
+
+fn list_dir(path: &str) -> Result<Vec<String>, io::Error> {
+	let mut names: Vec<String> = fs::read_dir(path)?
+		.filter_map(Result::ok)
+		.map(|entry| {
+			let mut name = entry.file_name().to_string_lossy().into_owned();
+			if entry.path().is_dir() {
+				name.push('/');
+			}
+			name
+		})
+		.collect();
+	names.sort();
+	Ok(names)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::io::Write;
+
+	#[test]
+	fn quoting_paths() {
+		assert_eq!(shell_quote("a b"), "'a b'");
+		assert_eq!(shell_quote("it's"), "'it'\\''s'");
+	}
+
+	#[test]
+	fn running_commands() {
+		assert_eq!(
+			run("echo {}; echo oops >&2", "it's here", TIMEOUT, &mut || {
+				false
+			}),
+			Some(vec!["it's here".to_string(), "oops".to_string()])
+		);
+	}
+
+	#[test]
+	fn hanging_commands_are_killed() {
+		let start = Instant::now();
+		let timeout = Duration::from_millis(100);
+		assert_eq!(
+			run("exec sleep 10", "a", timeout, &mut || false),
+			Some(vec![
+				"Command `exec sleep 10` timed out after 0.1s".to_string()
+			])
+		);
+		assert_eq!(run("exec sleep 10", "a", TIMEOUT, &mut || true), None);
+		assert!(start.elapsed() < Duration::from_secs(5));
+	}
+
+	#[test]
+	fn commands_started_by_the_shell_are_killed() {
+		let dir = tempfile::tempdir().unwrap();
+		let pid_file = dir.path().join("pid");
+		// The inner shell becomes `sleep`, which isn't the last command so is
+		// run as a child of the outer one
+		let cmd = "sh -c 'echo $$ > {}; exec sleep 10'; echo x";
+		let pid_path = pid_file.to_str().unwrap();
+		assert_eq!(run(cmd, pid_path, TIMEOUT, &mut || pid_file.exists()), None);
+
+		let pid = fs::read_to_string(&pid_file).unwrap();
+		let stat = format!("/proc/{}/stat", pid.trim());
+		let gone = || {
+			// Exited, or only left to be reaped
+			fs::read_to_string(&stat).map_or(true, |s| s.contains(") Z "))
+		};
+		let start = Instant::now();
+		while !gone() && start.elapsed() < Duration::from_secs(2) {
+			thread::sleep(POLL);
+		}
+		assert!(gone());
+	}
+
+	fn write(path: &std::path::Path, bytes: &[u8]) -> String {
+		fs::File::create(path).unwrap().write_all(bytes).unwrap();
+		path.to_str().unwrap().to_string()
+	}
+
+	#[test]
+	fn builtin_previews() {
+		let dir = tempfile::tempdir().unwrap();
+		let file = write(&dir.path().join("a.txt"), b"one\ntwo\n");
+		fs::create_dir(dir.path().join("b")).unwrap();
+
+		let lines = builtin(&file, false, Depth::None);
+		assert!(lines[0].starts_with("8  2"));
+		assert_eq!(lines[1..], ["   1 one", "   2 two"]);
+
+		let lines = builtin(dir.path().to_str().unwrap(), true, Depth::None);
+		assert!(lines[0].starts_with("2 entries  2"));
+		assert_eq!(lines[1..], ["a.txt", "b/"]);
+
+		assert!(builtin("/does/not/exist", false, Depth::None)[0].starts_with("Cannot preview"));
+	}
+
+	#[test]
+	fn previews_are_highlighted() {
+		let dir = tempfile::tempdir().unwrap();
+		let file = write(&dir.path().join("a.rs"), b"fn f() {}\n");
+		assert_eq!(
+			builtin(&file, false, Depth::Ansi16)[1],
+			"\u{1b}[90m   1\u{1b}[39m \u{1b}[35mfn\u{1b}[39m f() {}"
+		);
+	}
+
+	#[test]
+	fn detecting_binary_files() {
+		assert!(!is_binary(b"plain text\n"));
+		assert!(!is_binary("caf\u{e9}".as_bytes()));
+		// A character cut off at the end of what was read
+		assert!(!is_binary(&"caf\u{e9}".as_bytes()[..4]));
+		assert!(is_binary(b"ELF\x00\x01"));
+		assert!(is_binary(b"\xff\xfe text"));
+
+		let dir = tempfile::tempdir().unwrap();
+		let file = write(&dir.path().join("a.bin"), b"\x7fELF\x00");
+		assert_eq!(
+			builtin(&file, false, Depth::None)[1..],
+			["Binary file, not shown"]
+		);
+	}
+}
@@ -0,0 +1,259 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+use crate::path::{ArcPath, Path};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// The order in which the children of each directory are shown.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SortBy {
+	#[default]
+	Name,
+	Natural,
+	DirsFirst,
+	Mtime,
+	Size,
+	Extension,
+}
+
+impl SortBy {
+	pub const ALL: [SortBy; 6] = [
+		SortBy::Name,
+		SortBy::Natural,
+		SortBy::DirsFirst,
+		SortBy::Mtime,
+		SortBy::Size,
+		SortBy::Extension,
+	];
+
+	pub fn as_str(self) -> &'static str {
+		match self {
+			SortBy::Name => "name",
+			SortBy::Natural => "natural",
+			SortBy::DirsFirst => "dirs-first",
+			SortBy::Mtime => "mtime",
+			SortBy::Size => "size",
+			SortBy::Extension => "extension",
+		}
+	}
+
+	/// The sort order which follows this one, wrapping around to the first.
+	pub fn next(self) -> SortBy {
+		let i = SortBy::ALL.iter().position(|&s| s == self).unwrap();
+		SortBy::ALL[(i + 1) % SortBy::ALL.len()]
+	}
+
+	fn cmp(self, a: &Path, b: &Path) -> Ordering {
+		let by_name = || a.name.cmp(&b.name);
+		match self {
+			SortBy::Name => by_name(),
+			SortBy::Natural => natural_cmp(&a.name, &b.name),
+			SortBy::DirsFirst => b.is_dir.cmp(&a.is_dir).then_with(by_name),
+			// Newest and largest first, as that is what is usually looked for.
+			// Directories are sized by the files below them.
+			SortBy::Mtime => b.modified.cmp(&a.modified).then_with(by_name),
+			SortBy::Size => b.total_size.cmp(&a.total_size).then_with(by_name),
+			SortBy::Extension => extension(&a.name)
+				.cmp(extension(&b.name))
+				.then_with(by_name),
+		}
+	}
+}
+
+impl fmt::Display for SortBy {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl FromStr for SortBy {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		SortBy::ALL
+			.iter()
+			.find(|by| by.as_str() == s)
+			.copied()
+			.ok_or_else(|| format!("unknown sort order: {}", s))
+	}
+}
+
+/// The extension of a file name, empty if there is none. Leading dots (as in
+/// `.bashrc`) do not start an extension.
+fn extension(name: &str) -> &str {
+	match name.rfind('.') {
+		Some(i) if i > 0 => &name[i + 1..],
+		_ => "",
+	}
+}
+
+/// Compare strings such that runs of digits are compared by their numeric
+/// value, e.g. `file2` comes before `file10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+	let (mut a, mut b) = (a, b);
+	loop {
+		let (ca, cb) = match (a.chars().next(), b.chars().next()) {
+			(None, None) => return Ordering::Equal,
+			(None, Some(_)) => return Ordering::Less,
+			(Some(_), None) => return Ordering::Greater,
+			(Some(ca), Some(cb)) => (ca, cb),
+		};
+
+		if ca.is_ascii_digit() && cb.is_ascii_digit() {
+			let (na, ra) = split_digits(a);
+			let (nb, rb) = split_digits(b);
+			let (ta, tb) = (na.trim_start_matches('0'), nb.trim_start_matches('0'));
+			let ord = ta
+				.len()
+				.cmp(&tb.len())
+				.then_with(|| ta.cmp(tb))
+				.then_with(|| na.len().cmp(&nb.len()));
+			if ord != Ordering::Equal {
+				return ord;
+			}
+			a = ra;
+			b = rb;
+		} else {
+			if ca != cb {
+				return ca.cmp(&cb);
+			}
+			a = &a[ca.len_utf8()..];
+			b = &b[cb.len_utf8()..];
+		}
+	}
+}
+
+fn split_digits(s: &str) -> (&str, &str) {
+	let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+	s.split_at(end)
+}
+
+/// Sort the children of every directory below `node` and push all paths, in
+/// display order, onto `paths`.
+fn sort_node(node: &ArcPath, by: SortBy, reverse: bool, paths: &mut Vec<ArcPath>) {
+	paths.push(node.clone());
+	let children = {
+		let mut pth = node.borrow_mut();
+		match pth.children.as_mut() {
+			Some(children) => {
+				children.sort_by(|a, b| {
+					let ord = by.cmp(&a.borrow(), &b.borrow());
+					if reverse {
+						ord.reverse()
+					} else {
+						ord
+					}
+				});
+				children.clone()
+			}
+			None => return,
+		}
+	};
+	for child in &children {
+		sort_node(child, by, reverse, paths);
+	}
+}
+
+/// Sort the siblings of each directory in the tree rooted at `root`. Returns
+/// all paths in their new display order.
+pub fn sort_paths(root: &ArcPath, by: SortBy, reverse: bool) -> Vec<ArcPath> {
+	let mut paths = Vec::new();
+	sort_node(root, by, reverse, &mut paths);
+	paths
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::path::{build_paths, Meta};
+	use std::time::{Duration, UNIX_EPOCH};
+
+	fn joined(paths: &[ArcPath]) -> Vec<String> {
+		paths.iter().map(|p| p.joined()).collect()
+	}
+
+	#[test]
+	fn natural_ordering() {
+		assert_eq!(natural_cmp("file2", "file10"), Ordering::Less);
+		assert_eq!(natural_cmp("file10", "file10"), Ordering::Equal);
+		assert_eq!(natural_cmp("a10b", "a10a"), Ordering::Greater);
+		assert_eq!(natural_cmp("a02", "a2"), Ordering::Greater);
+		assert_eq!(natural_cmp("a", "a1"), Ordering::Less);
+	}
+
+	#[test]
+	fn parsing_and_cycling() {
+		assert_eq!("dirs-first".parse(), Ok(SortBy::DirsFirst));
+		assert!("nope".parse::<SortBy>().is_err());
+		assert_eq!(SortBy::Name.next(), SortBy::Natural);
+		assert_eq!(SortBy::Extension.next(), SortBy::Name);
+	}
+
+	#[test]
+	fn extensions() {
+		assert_eq!(extension("a.tar.gz"), "gz");
+		assert_eq!(extension(".bashrc"), "");
+		assert_eq!(extension("Makefile"), "");
+	}
+
+	#[test]
+	fn siblings_are_sorted_per_directory() {
+		let paths = build_paths(vec![
+			("f10.c", false),
+			("f2.c", false),
+			("src", true),
+			("src/b.rs", false),
+			("src/a.rs", false),
+		]);
+		let root = paths[0].clone();
+
+		let sorted = sort_paths(&root, SortBy::Name, false);
+		assert_eq!(
+			joined(&sorted),
+			vec![".", "f10.c", "f2.c", "src", "src/a.rs", "src/b.rs"]
+		);
+
+		let sorted = sort_paths(&root, SortBy::Natural, false);
+		assert_eq!(
+			joined(&sorted),
+			vec![".", "f2.c", "f10.c", "src", "src/a.rs", "src/b.rs"]
+		);
+
+		let sorted = sort_paths(&root, SortBy::DirsFirst, false);
+		assert_eq!(
+			joined(&sorted),
+			vec![".", "src", "src/a.rs", "src/b.rs", "f10.c", "f2.c"]
+		);
+
+		let sorted = sort_paths(&root, SortBy::Name, true);
+		assert_eq!(
+			joined(&sorted),
+			vec![".", "src", "src/b.rs", "src/a.rs", "f2.c", "f10.c"]
+		);
+	}
+
+	#[test]
+	fn sorting_by_metadata() {
+		let meta = |size, secs| Meta {
+			size,
+			modified: Some(UNIX_EPOCH + Duration::from_secs(secs)),
+			..Meta::default()
+		};
+		let paths = build_paths(vec![
+			("a", meta(1, 30)),
+			("b", meta(3, 10)),
+			("c", meta(2, 20)),
+		]);
+		let root = paths[0].clone();
+
+		let sorted = sort_paths(&root, SortBy::Size, false);
+		assert_eq!(joined(&sorted), vec![".", "b", "c", "a"]);
+
+		let sorted = sort_paths(&root, SortBy::Mtime, false);
+		assert_eq!(joined(&sorted), vec![".", "a", "c", "b"]);
+	}
+}
@@ -0,0 +1,443 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+use crate::path::Path;
+use crate::theme::Theme;
+use std::cmp;
+use std::env;
+use std::io;
+
+/// Nerd Font glyphs for directories, which need a patched font.
+pub const DIR_OPEN: &str = "\u{f07c}  ";
+pub const DIR_CLOSED: &str = "\u{f07b}  ";
+/// Directory markers which any font can show.
+pub const ASCII_OPEN: &str = "- ";
+pub const ASCII_CLOSED: &str = "+ ";
+const FILE: &str = "\u{f016}  ";
+
+/// The colours `ls` uses when `LS_COLORS` is not set.
+const DEFAULT_LS_COLORS: &str = "di=01;34:ln=01;36:or=01;31:pi=33:so=01;35:ex=01;32";
+/// The code which the match highlighting uses to reset the foreground colour.
+const FG_RESET: &str = "\u{1b}[39m";
+
+/// Icons shown before file names, by extension. Icons are padded as wide as
+/// the directory glyphs.
+const ICONS: &[(&str, &str)] = &[
+	("c", "\u{e61e}  "),
+	("cpp", "\u{e61d}  "),
+	("css", "\u{e749}  "),
+	("gif", "\u{f1c5}  "),
+	("go", "\u{e627}  "),
+	("gz", "\u{f1c6}  "),
+	("h", "\u{e61e}  "),
+	("html", "\u{e736}  "),
+	("jpg", "\u{f1c5}  "),
+	("js", "\u{e74e}  "),
+	("json", "\u{e60b}  "),
+	("lock", "\u{f023}  "),
+	("md", "\u{e73e}  "),
+	("pdf", "\u{f1c1}  "),
+	("png", "\u{f1c5}  "),
+	("py", "\u{e73c}  "),
+	("rs", "\u{e7a8}  "),
+	("sh", "\u{e795}  "),
+	("svg", "\u{f1c5}  "),
+	("tar", "\u{f1c6}  "),
+	("toml", "\u{e615}  "),
+	("ts", "\u{e628}  "),
+	("txt", "\u{f0f6}  "),
+	("yaml", "\u{e615}  "),
+	("yml", "\u{e615}  "),
+	("zip", "\u{f1c6}  "),
+];
+
+/// Colours for file names, as given by `LS_COLORS`. Each colour is a list of
+/// SGR codes, e.g. `01;34`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LsColors {
+	dir: Option<String>,
+	link: Option<String>,
+	orphan: Option<String>,
+	exec: Option<String>,
+	file: Option<String>,
+	fifo: Option<String>,
+	socket: Option<String>,
+	/// Colours for names ending with a suffix, e.g. `*.rs=33`
+	suffixes: Vec<(String, String)>,
+}
+
+impl LsColors {
+	/// Read colours from `LS_COLORS`, falling back to the defaults of `ls`.
+	pub fn from_env() -> Self {
+		match env::var("LS_COLORS") {
+			Ok(spec) if !spec.is_empty() => Self::parse(&spec),
+			_ => Self::parse(DEFAULT_LS_COLORS),
+		}
+	}
+
+	/// Parse an `LS_COLORS` specification. Unknown entries are ignored.
+	pub fn parse(spec: &str) -> Self {
+		let mut colors = LsColors::default();
+		for entry in spec.split(':') {
+			let (key, value) = match entry.find('=') {
+				Some(i) => (&entry[..i], entry[i + 1..].to_string()),
+				None => continue,
+			};
+			if value.is_empty() {
+				continue;
+			}
+			match key {
+				"di" => colors.dir = Some(value),
+				"ln" => colors.link = Some(value),
+				"or" => colors.orphan = Some(value),
+				"ex" => colors.exec = Some(value),
+				"fi" => colors.file = Some(value),
+				"pi" => colors.fifo = Some(value),
+				"so" => colors.socket = Some(value),
+				_ if key.starts_with('*') => colors.suffixes.push((key[1..].to_string(), value)),
+				_ => (),
+			}
+		}
+		colors
+	}
+
+	/// The SGR codes to colour `pth` with, if any.
+	fn style(&self, pth: &Path) -> Option<&str> {
+		if let Some(link) = &pth.link {
+			let style = if link.broken {
+				self.orphan.as_ref().or(self.link.as_ref())
+			} else {
+				self.link.as_ref()
+			};
+			// `target` means links are coloured as the path they point to
+			if style.is_some_and(|s| s != "target") {
+				return style.map(|s| s.as_str());
+			}
+		}
+
+		let style = match pth.mode & 0o170000 {
+			_ if pth.is_dir => self.dir.as_ref(),
+			0o010000 => self.fifo.as_ref(),
+			0o140000 => self.socket.as_ref(),
+			_ if pth.mode & 0o111 != 0 => self.exec.as_ref(),
+			_ => None,
+		};
+		style
+			.or_else(|| {
+				self.suffixes
+					.iter()
+					.find(|(suffix, _)| pth.name.ends_with(suffix.as_str()))
+					.map(|(_, style)| style)
+			})
+			.or(self.file.as_ref())
+			.map(|s| s.as_str())
+	}
+}
+
+/// The lines drawn between a directory and its children.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TreeStyle {
+	Unicode,
+	Ascii,
+	Rounded,
+	IndentOnly,
+}
+
+impl TreeStyle {
+	pub fn parse(s: &str) -> Option<TreeStyle> {
+		match s {
+			"unicode" => Some(TreeStyle::Unicode),
+			"ascii" => Some(TreeStyle::Ascii),
+			"rounded" => Some(TreeStyle::Rounded),
+			"indent-only" => Some(TreeStyle::IndentOnly),
+			_ => None,
+		}
+	}
+}
+
+/// The strings which make up the indentation of each line, all `width`
+/// characters wide.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Guides {
+	/// Below a path which has later siblings
+	pub continuation: String,
+	/// Below a path's last sibling
+	pub end: String,
+	/// Before a path which has later siblings
+	pub branch: String,
+	/// Before a path's last sibling
+	pub last: String,
+}
+
+impl Guides {
+	/// Guides in `style`, indenting each level by `width` characters. The
+	/// width is at least two, or one when only indenting.
+	pub fn new(style: TreeStyle, width: usize) -> Self {
+		let (vertical, branch, last, horizontal) = match style {
+			TreeStyle::Unicode => ('│', '├', '└', '─'),
+			TreeStyle::Ascii => ('|', '|', '`', '-'),
+			TreeStyle::Rounded => ('│', '├', '╰', '─'),
+			TreeStyle::IndentOnly => {
+				let indent = " ".repeat(cmp::max(1, width));
+				return Guides {
+					continuation: indent.clone(),
+					end: indent.clone(),
+					branch: indent.clone(),
+					last: indent,
+				};
+			}
+		};
+
+		let width = cmp::max(2, width);
+		let line = horizontal.to_string().repeat(width - 2);
+		Guides {
+			continuation: format!("{}{}", vertical, " ".repeat(width - 1)),
+			end: " ".repeat(width),
+			branch: format!("{}{} ", branch, line),
+			last: format!("{}{} ", last, line),
+		}
+	}
+}
+
+impl Default for Guides {
+	fn default() -> Self {
+		Guides::new(TreeStyle::Unicode, 4)
+	}
+}
+
+/// How file names are decorated in the tree.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Style {
+	pub colors: LsColors,
+	/// Show Nerd Font icons, rather than ASCII directory markers
+	pub icons: bool,
+	pub theme: Theme,
+	pub guides: Guides,
+	/// Write no escape sequences at all, e.g. when not writing to a terminal
+	pub plain: bool,
+}
+
+impl Style {
+	/// The marker shown before the name of `pth`.
+	pub fn marker(&self, pth: &Path) -> &'static str {
+		match (pth.is_dir, self.icons) {
+			(true, true) if pth.open => DIR_OPEN,
+			(true, true) => DIR_CLOSED,
+			(true, false) if pth.open => ASCII_OPEN,
+			(true, false) => ASCII_CLOSED,
+			(false, true) => file_icon(&pth.name),
+			(false, false) => "",
+		}
+	}
+
+	/// Colour `name`, which may contain highlighted matches, as the name of
+	/// `pth`.
+	pub fn paint(&self, pth: &Path, name: &str) -> String {
+		let fg = self.theme.fg.fg_sgr();
+		let style = self
+			.colors
+			.style(pth)
+			.or(Some(fg.as_str()).filter(|s| !s.is_empty()));
+		match style {
+			Some(style) => {
+				let start = format!("\u{1b}[{}m", style);
+				// Restore the name's colour after each highlighted match
+				let name = name.replace(FG_RESET, &format!("{}{}", FG_RESET, start));
+				format!("{}{}{}", start, name, reset(style))
+			}
+			None => name.to_string(),
+		}
+	}
+}
+
+/// Whether to show icons for `--icons=WHEN`. With `auto`, icons are shown
+/// when the terminal looks able to show them, though whether a Nerd Font is
+/// in use can't be told.
+#[cfg_attr(tarpaulin, skip)]
+pub fn icons_enabled(when: &str) -> bool {
+	match when {
+		"always" => true,
+		"never" => false,
+		_ => {
+			let term = env::var("TERM").unwrap_or_default();
+			let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+				.iter()
+				.find_map(|v| env::var(v).ok().filter(|l| !l.is_empty()))
+				.unwrap_or_default()
+				.to_uppercase();
+			termion::is_tty(&io::stdout())
+				&& term != "linux"
+				&& term != "dumb"
+				&& (locale.contains("UTF-8") || locale.contains("UTF8"))
+		}
+	}
+}
+
+fn file_icon(name: &str) -> &'static str {
+	let ext = match name.rfind('.') {
+		Some(i) if i > 0 => name[i + 1..].to_lowercase(),
+		_ => return FILE,
+	};
+	ICONS
+		.binary_search_by(|(e, _)| (*e).cmp(ext.as_str()))
+		.map_or(FILE, |i| ICONS[i].1)
+}
+
+/// The SGR codes which undo `style`. A full reset is avoided so as not to
+/// clear the background of the highlighted line.
+fn reset(style: &str) -> String {
+	let mut codes = Vec::new();
+	let mut iter = style.split(';').map(|c| c.parse::<u8>().unwrap_or(0));
+	while let Some(code) = iter.next() {
+		let undo = match code {
+			1 | 2 => 22,
+			3 => 23,
+			4 => 24,
+			5 | 6 => 25,
+			7 => 27,
+			8 => 28,
+			9 => 29,
+			30..=37 | 90..=97 => 39,
+			40..=47 | 100..=107 => 49,
+			38 | 48 => {
+				// Skip the arguments of 256 (`5;N`) and true (`2;R;G;B`) colours
+				let skip = if iter.next() == Some(5) { 1 } else { 3 };
+				for _ in 0..skip {
+					iter.next();
+				}
+				code + 1
+			}
+			_ => continue,
+		};
+		if !codes.contains(&undo) {
+			codes.push(undo);
+		}
+	}
+	if codes.is_empty() {
+		return String::new();
+	}
+	let codes: Vec<String> = codes.iter().map(|c| c.to_string()).collect();
+	format!("\u{1b}[{}m", codes.join(";"))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::path::{Link, Meta};
+
+	fn path(name: &str, meta: Meta) -> crate::path::ArcPath {
+		Path::new(name.into(), meta)
+	}
+
+	#[test]
+	fn icons_are_sorted() {
+		assert!(ICONS.windows(2).all(|w| w[0].0 < w[1].0));
+	}
+
+	#[test]
+	fn parsing_ls_colors() {
+		let colors = LsColors::parse("di=01;34:ln=target:*.rs=33:bad:xx=1:fi=");
+		assert_eq!(colors.dir.as_deref(), Some("01;34"));
+		assert_eq!(colors.link.as_deref(), Some("target"));
+		assert_eq!(colors.file, None);
+		assert_eq!(colors.suffixes, vec![(".rs".to_string(), "33".to_string())]);
+	}
+
+	#[test]
+	fn styles_by_file_type() {
+		let colors = LsColors::parse(DEFAULT_LS_COLORS);
+		let style = |name, meta| colors.style(&path(name, meta).borrow()).map(String::from);
+		let link = |broken| Meta {
+			link: Some(Link {
+				target: "x".into(),
+				broken,
+			}),
+			..Meta::default()
+		};
+
+		assert_eq!(style("d", Meta::from(true)).as_deref(), Some("01;34"));
+		assert_eq!(style("f", Meta::default()), None);
+		let exec = Meta {
+			mode: 0o100755,
+			..Meta::default()
+		};
+		assert_eq!(style("e", exec).as_deref(), Some("01;32"));
+		assert_eq!(style("l", link(false)).as_deref(), Some("01;36"));
+		assert_eq!(style("o", link(true)).as_deref(), Some("01;31"));
+
+		let colors = LsColors::parse("ln=target:*.rs=33");
+		let style = |name, meta| colors.style(&path(name, meta).borrow()).map(String::from);
+		assert_eq!(style("a.rs", link(false)).as_deref(), Some("33"));
+		assert_eq!(style("a.c", link(false)), None);
+	}
+
+	#[test]
+	fn painting_restores_color_after_matches() {
+		let style = Style {
+			colors: LsColors::parse("*.rs=01;33"),
+			..Style::default()
+		};
+		let pth = path("a.rs", Meta::default());
+		let name = format!("\u{1b}[38;5;12ma{}.rs", FG_RESET);
+		assert_eq!(
+			style.paint(&pth.borrow(), &name),
+			"\u{1b}[01;33m\u{1b}[38;5;12ma\u{1b}[39m\u{1b}[01;33m.rs\u{1b}[22;39m"
+		);
+	}
+
+	#[test]
+	fn resetting_styles() {
+		assert_eq!(reset("01;34"), "\u{1b}[22;39m");
+		assert_eq!(reset("38;5;208;48;2;1;2;3;4"), "\u{1b}[39;49;24m");
+		assert_eq!(reset("40;31;01"), "\u{1b}[49;39;22m");
+	}
+
+	#[test]
+	fn tree_guides() {
+		let guides = Guides::default();
+		assert_eq!(
+			(
+				&*guides.continuation,
+				&*guides.end,
+				&*guides.branch,
+				&*guides.last
+			),
+			("│   ", "    ", "├── ", "└── ")
+		);
+
+		let guides = Guides::new(TreeStyle::Ascii, 3);
+		assert_eq!(
+			(
+				&*guides.continuation,
+				&*guides.end,
+				&*guides.branch,
+				&*guides.last
+			),
+			("|  ", "   ", "|- ", "`- ")
+		);
+
+		let guides = Guides::new(TreeStyle::Rounded, 0);
+		assert_eq!((&*guides.branch, &*guides.last), ("├ ", "╰ "));
+
+		let guides = Guides::new(TreeStyle::IndentOnly, 2);
+		assert_eq!((&*guides.continuation, &*guides.last), ("  ", "  "));
+	}
+
+	#[test]
+	fn markers() {
+		let mut style = Style::default();
+		let dir = path("d", Meta::from(true));
+		let file = path("main.rs", Meta::default());
+		assert_eq!(style.marker(&dir.borrow()), ASCII_OPEN);
+		assert_eq!(style.marker(&file.borrow()), "");
+
+		style.icons = true;
+		dir.flip_open();
+		assert_eq!(style.marker(&dir.borrow()), DIR_CLOSED);
+		assert_eq!(style.marker(&file.borrow()), "\u{e7a8}  ");
+		assert_eq!(file_icon("README"), FILE);
+		assert_eq!(file_icon("x.unknown"), FILE);
+	}
+}
@@ -0,0 +1,235 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+use std::iter;
+use unicode_width::UnicodeWidthChar;
+
+const TAB_WIDTH: usize = 4;
+/// Marks where a line has been cut.
+pub const ELLIPSIS: char = '…';
+
+/// Part of a line as it is drawn on a terminal.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Piece<'a> {
+	/// An SGR escape sequence, which changes colours
+	Style(&'a str),
+	Char(char),
+}
+
+/// Number of columns `c` takes up. Combining marks and other zero-width
+/// characters take none, and wide characters such as CJK take two.
+pub fn char_width(c: char) -> usize {
+	c.width().unwrap_or(0)
+}
+
+/// Split `line` into styles and characters. Escape sequences other than SGR
+/// and control characters are dropped, and tabs are expanded to spaces.
+fn pieces(line: &str) -> Vec<Piece<'_>> {
+	let mut pieces = Vec::with_capacity(line.len());
+	let mut col = 0;
+	let mut chars = line.char_indices().peekable();
+
+	while let Some((i, c)) = chars.next() {
+		match c {
+			'\x1b' => {
+				if chars.peek().map(|&(_, c)| c) != Some('[') {
+					continue;
+				}
+				chars.next();
+				let end = chars
+					.find(|&(_, c)| ('\x40'..='\x7e').contains(&c))
+					.map(|(j, c)| j + c.len_utf8());
+				if let Some(end) = end.filter(|&end| line[..end].ends_with('m')) {
+					pieces.push(Piece::Style(&line[i..end]));
+				}
+			}
+			'\t' => {
+				let n = TAB_WIDTH - col % TAB_WIDTH;
+				pieces.extend(iter::repeat_n(Piece::Char(' '), n));
+				col += n;
+			}
+			c if c.is_control() => {}
+			c => {
+				pieces.push(Piece::Char(c));
+				col += 1;
+			}
+		}
+	}
+	pieces
+}
+
+/// Number of columns `line` takes up on a terminal.
+pub fn width(line: &str) -> usize {
+	pieces(line)
+		.iter()
+		.map(|p| match p {
+			Piece::Char(c) => char_width(*c),
+			Piece::Style(_) => 0,
+		})
+		.sum()
+}
+
+/// Cut `line` down to the `width` columns starting at column `skip`, with an
+/// ellipsis on either side where it was cut. Colours are kept, but other
+/// escape sequences and control characters are dropped and tabs are expanded.
+/// A wide character which only partly fits is replaced by spaces.
+pub fn clip(line: &str, skip: usize, width: usize) -> String {
+	if width == 0 {
+		return String::new();
+	}
+	let pieces = pieces(line);
+	let total: usize = pieces
+		.iter()
+		.map(|p| match p {
+			Piece::Char(c) => char_width(*c),
+			Piece::Style(_) => 0,
+		})
+		.sum();
+	let cut_left = skip > 0;
+	let cut_right = total > skip + width;
+	// Characters wholly within these columns are drawn as they are
+	let lo = skip + cut_left as usize;
+	let hi = skip + width - cut_right as usize;
+
+	let mut clipped = String::with_capacity(line.len());
+	let mut styled = false;
+	let mut col = 0;
+	// Whether the last character drawn is the one marks would combine with
+	let mut drawn = false;
+	for piece in pieces {
+		match piece {
+			Piece::Style(style) => {
+				clipped.push_str(style);
+				styled = true;
+			}
+			Piece::Char(c) => {
+				let (start, end) = (col, col + char_width(c));
+				if start == end {
+					if drawn {
+						clipped.push(c);
+					}
+					continue;
+				}
+				if start >= skip + width {
+					break;
+				}
+				col = end;
+				drawn = start >= lo && end <= hi;
+				if drawn {
+					clipped.push(c);
+					continue;
+				}
+				for x in start.max(skip)..end.min(skip + width) {
+					if (x == skip && cut_left) || (x == skip + width - 1 && cut_right) {
+						clipped.push(ELLIPSIS);
+					} else {
+						clipped.push(' ');
+					}
+				}
+			}
+		}
+	}
+
+	if styled {
+		clipped.push_str("\x1b[0m");
+	}
+	clipped
+}
+
+/// The columns spanned by the last run of text in `line` which starts with the
+/// style `start`, up to the next change of style.
+pub fn last_styled(line: &str, start: &str) -> Option<(usize, usize)> {
+	let mut span = None;
+	let mut open = None;
+	let mut col = 0;
+	for piece in pieces(line) {
+		match piece {
+			Piece::Style(style) if style == start => open = Some(col),
+			Piece::Style(_) => {
+				if let Some(from) = open.take() {
+					span = Some((from, col));
+				}
+			}
+			Piece::Char(c) => col += char_width(c),
+		}
+	}
+	open.map(|from| (from, col)).or(span)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	const RED: &str = "\x1b[31m";
+	const END: &str = "\x1b[39m";
+	const RESET: &str = "\x1b[0m";
+
+	#[test]
+	fn measuring_width() {
+		assert_eq!(width("abc"), 3);
+		assert_eq!(width(&format!("{}abc{}", RED, END)), 3);
+		assert_eq!(width("a\tb"), 5);
+		assert_eq!(width("a\rb\x07\x1b[2J"), 2);
+		assert_eq!(width("日本"), 4);
+		assert_eq!(width("cafe\u{301}"), 4);
+		assert_eq!(width("\u{5e9}\u{5c1}"), 1);
+	}
+
+	#[test]
+	fn clipping_lines() {
+		assert_eq!(clip("abcdef", 0, 6), "abcdef");
+		assert_eq!(clip("abcdef", 0, 4), "abc…");
+		assert_eq!(clip("abcdef", 2, 4), "…def");
+		assert_eq!(clip("abcdefgh", 2, 4), "…de…");
+		assert_eq!(clip("abc", 5, 4), "");
+		assert_eq!(clip("abc", 0, 0), "");
+	}
+
+	#[test]
+	fn clipping_wide_characters() {
+		assert_eq!(clip("ab日本", 0, 6), "ab日本");
+		assert_eq!(clip("ab日本", 0, 5), "ab日…");
+		assert_eq!(clip("ab日本", 0, 4), "ab …");
+		assert_eq!(clip("日本語", 1, 4), "…本…");
+		assert_eq!(clip("日本語", 2, 4), "… 語");
+		assert_eq!(clip("日本", 0, 1), "…");
+	}
+
+	#[test]
+	fn clipping_keeps_combining_marks() {
+		assert_eq!(clip("cafe\u{301}s", 0, 5), "cafe\u{301}s");
+		assert_eq!(clip("cafe\u{301}s", 0, 4), "caf…");
+		assert_eq!(clip("cafe\u{301}s", 1, 4), "…fe\u{301}s");
+		assert_eq!(clip("e\u{301}abc", 1, 4), "…bc");
+	}
+
+	#[test]
+	fn clipping_keeps_colors() {
+		let line = format!("a{}bcd{}e", RED, END);
+		assert_eq!(clip(&line, 0, 3), format!("a{}b…{}", RED, RESET));
+		assert_eq!(clip(&line, 0, 5), format!("a{}bcd{}e{}", RED, END, RESET));
+	}
+
+	#[test]
+	fn clipping_drops_control_characters() {
+		assert_eq!(clip("a\tb", 0, 8), "a   b");
+		assert_eq!(clip("a\tb", 0, 3), "a …");
+		assert_eq!(clip("a\rb\x07", 0, 8), "ab");
+		assert_eq!(clip("\x1b[2Jab", 0, 8), "ab");
+		assert_eq!(clip("ab\x1b[", 0, 8), "ab");
+	}
+
+	#[test]
+	fn finding_styled_text() {
+		let line = format!("{}a{}b{}cd{}e", RED, END, RED, END);
+		assert_eq!(last_styled(&line, RED), Some((2, 4)));
+		assert_eq!(last_styled(&format!("ab{}c", RED), RED), Some((2, 3)));
+		assert_eq!(last_styled("abc", RED), None);
+		assert_eq!(
+			last_styled(&format!("日{}本{}", RED, END), RED),
+			Some((2, 4))
+		);
+	}
+}
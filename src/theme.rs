@@ -0,0 +1,468 @@
+// Copyright ⓒ 2019-2020 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms.
+
+use std::env;
+
+/// RGB values of the 16 basic terminal colours, as shown by xterm.
+const BASIC_RGB: [(u8, u8, u8); 16] = [
+	(0, 0, 0),
+	(205, 0, 0),
+	(0, 205, 0),
+	(205, 205, 0),
+	(0, 0, 238),
+	(205, 0, 205),
+	(0, 205, 205),
+	(229, 229, 229),
+	(127, 127, 127),
+	(255, 0, 0),
+	(0, 255, 0),
+	(255, 255, 0),
+	(92, 92, 255),
+	(255, 0, 255),
+	(0, 255, 255),
+	(255, 255, 255),
+];
+const BASIC_NAMES: [&str; 8] = [
+	"black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+/// Levels of each component in the 6x6x6 colour cube of 256 colour terminals.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// A terminal colour.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Color {
+	/// The terminal's own colour
+	Default,
+	/// One of the 16 basic colours
+	Basic(u8),
+	/// One of the 256 indexed colours
+	Indexed(u8),
+	Rgb(u8, u8, u8),
+}
+
+impl Color {
+	/// Parse a colour given as `-1` (the default), a number from 0 to 255, a
+	/// `#rrggbb` hex code or a name such as `blue` or `bright-blue`.
+	pub fn parse(s: &str) -> Result<Color, String> {
+		let invalid = || format!("invalid color: {}", s);
+		if s == "-1" {
+			return Ok(Color::Default);
+		}
+		if let Ok(n) = s.parse::<u8>() {
+			return Ok(Color::Indexed(n));
+		}
+		if let Some(hex) = s.strip_prefix('#') {
+			if hex.len() != 6 || !hex.is_ascii() {
+				return Err(invalid());
+			}
+			let component = |i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+			return Ok(Color::Rgb(component(0)?, component(2)?, component(4)?));
+		}
+
+		let (name, offset) = match s.strip_prefix("bright-") {
+			Some(name) => (name, 8),
+			None => (s, 0),
+		};
+		BASIC_NAMES
+			.iter()
+			.position(|&n| n == name)
+			.map(|i| Color::Basic(i as u8 + offset))
+			.ok_or_else(invalid)
+	}
+
+	/// Convert the colour to one which a terminal of `depth` can show.
+	pub fn with_depth(self, depth: Depth) -> Color {
+		match (depth, self) {
+			(Depth::None, _) => Color::Default,
+			(Depth::Ansi16, Color::Indexed(n)) if n < 16 => Color::Basic(n),
+			(Depth::Ansi16, Color::Indexed(n)) => Color::Basic(nearest_basic(indexed_rgb(n))),
+			(Depth::Ansi16, Color::Rgb(r, g, b)) => Color::Basic(nearest_basic((r, g, b))),
+			(Depth::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(rgb_to_indexed(r, g, b)),
+			(_, color) => color,
+		}
+	}
+
+	/// The SGR codes to use the colour as a foreground (or background when
+	/// `offset` is 10). Empty for the default colour.
+	fn sgr(self, offset: u8) -> String {
+		match self {
+			Color::Default => String::new(),
+			Color::Basic(n) if n < 8 => (30 + offset + n).to_string(),
+			Color::Basic(n) => (90 + offset + n - 8).to_string(),
+			Color::Indexed(n) => format!("{};5;{}", 38 + offset, n),
+			Color::Rgb(r, g, b) => format!("{};2;{};{};{}", 38 + offset, r, g, b),
+		}
+	}
+
+	/// SGR codes to use the colour as a foreground, e.g. `38;5;12`.
+	pub fn fg_sgr(self) -> String {
+		self.sgr(0)
+	}
+
+	/// The escape sequence to use the colour as a foreground.
+	pub fn fg(self) -> String {
+		escape(&self.sgr(0))
+	}
+
+	/// The escape sequence which undoes `fg`.
+	pub fn fg_reset(self) -> &'static str {
+		match self {
+			Color::Default => "",
+			_ => "\u{1b}[39m",
+		}
+	}
+
+	/// The escape sequence which undoes `bg`.
+	pub fn bg_reset(self) -> &'static str {
+		match self {
+			Color::Default => "",
+			_ => "\u{1b}[49m",
+		}
+	}
+
+	/// The escape sequence to use the colour as a background.
+	pub fn bg(self) -> String {
+		escape(&self.sgr(10))
+	}
+}
+
+fn escape(sgr: &str) -> String {
+	if sgr.is_empty() {
+		String::new()
+	} else {
+		format!("\u{1b}[{}m", sgr)
+	}
+}
+
+fn indexed_rgb(n: u8) -> (u8, u8, u8) {
+	match n {
+		0..=15 => BASIC_RGB[n as usize],
+		16..=231 => {
+			let n = n - 16;
+			let level = |i: u8| CUBE_LEVELS[i as usize];
+			(level(n / 36), level(n / 6 % 6), level(n % 6))
+		}
+		_ => {
+			let v = 8 + 10 * (n - 232);
+			(v, v, v)
+		}
+	}
+}
+
+fn rgb_to_indexed(r: u8, g: u8, b: u8) -> u8 {
+	let cube = |v: u8| match v {
+		0..=47 => 0,
+		48..=114 => 1,
+		_ => (v - 35) / 40,
+	};
+	let (cr, cg, cb) = (cube(r), cube(g), cube(b));
+	let cube_index = 16 + 36 * cr + 6 * cg + cb;
+
+	// Use the grey ramp if it is closer than the colour cube
+	let mean = ((r as u16 + g as u16 + b as u16) / 3) as u8;
+	let grey_index = if mean < 8 {
+		16
+	} else if mean > 238 {
+		231
+	} else {
+		232 + (mean - 8) / 10
+	};
+	if distance((r, g, b), indexed_rgb(grey_index)) < distance((r, g, b), indexed_rgb(cube_index)) {
+		grey_index
+	} else {
+		cube_index
+	}
+}
+
+fn nearest_basic(rgb: (u8, u8, u8)) -> u8 {
+	(0..16u8)
+		.min_by_key(|&i| distance(rgb, BASIC_RGB[i as usize]))
+		.unwrap()
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+	let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+	d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// The colours which a terminal supports, from least to most.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Depth {
+	None,
+	Ansi16,
+	Ansi256,
+	TrueColor,
+}
+
+impl Depth {
+	/// Guess the colours the terminal supports from the environment. No colours
+	/// are used if `NO_COLOR` is set (see <https://no-color.org>).
+	#[cfg_attr(tarpaulin, skip)]
+	pub fn detect() -> Depth {
+		let var = |name| env::var(name).unwrap_or_default();
+		Depth::from_env(&var("NO_COLOR"), &var("TERM"), &var("COLORTERM"))
+	}
+
+	fn from_env(no_color: &str, term: &str, colorterm: &str) -> Depth {
+		if !no_color.is_empty() || term == "dumb" {
+			Depth::None
+		} else if colorterm == "truecolor" || colorterm == "24bit" {
+			Depth::TrueColor
+		} else if term.contains("256") {
+			Depth::Ansi256
+		} else {
+			Depth::Ansi16
+		}
+	}
+}
+
+/// How matches are highlighted within names.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Highlight {
+	pub start: String,
+	pub end: String,
+}
+
+impl Highlight {
+	/// No highlighting.
+	pub fn none() -> Self {
+		Highlight {
+			start: String::new(),
+			end: String::new(),
+		}
+	}
+}
+
+impl Default for Highlight {
+	fn default() -> Self {
+		Theme::default().highlight()
+	}
+}
+
+/// The colours of each part of the interface.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Theme {
+	/// Names of paths which have no `LS_COLORS` colour
+	pub fg: Color,
+	/// Background of lines which are not under the cursor
+	pub bg: Color,
+	/// Background of the line under the cursor
+	pub bg_current: Color,
+	/// Matched text
+	pub hl: Color,
+	pub prompt: Color,
+	pub info: Color,
+	/// The cursor's `>`
+	pub pointer: Color,
+	/// The `>` marking selected paths
+	pub marker: Color,
+	/// The target of a broken symlink
+	pub broken: Color,
+	pub depth: Depth,
+}
+
+impl Default for Theme {
+	fn default() -> Self {
+		Theme::dark()
+	}
+}
+
+impl Theme {
+	pub fn dark() -> Self {
+		Theme {
+			fg: Color::Default,
+			bg: Color::Default,
+			bg_current: Color::Rgb(50, 50, 50),
+			hl: Color::Indexed(12),
+			prompt: Color::Indexed(4),
+			info: Color::Indexed(10),
+			pointer: Color::Indexed(1),
+			marker: Color::Indexed(9),
+			broken: Color::Indexed(9),
+			depth: Depth::TrueColor,
+		}
+	}
+
+	pub fn light() -> Self {
+		Theme {
+			bg_current: Color::Rgb(220, 220, 220),
+			hl: Color::Indexed(27),
+			info: Color::Indexed(28),
+			marker: Color::Indexed(160),
+			broken: Color::Indexed(160),
+			..Theme::dark()
+		}
+	}
+
+	/// A theme which only uses the 16 basic colours.
+	pub fn basic() -> Self {
+		Theme {
+			bg_current: Color::Basic(0),
+			hl: Color::Basic(12),
+			prompt: Color::Basic(4),
+			info: Color::Basic(10),
+			pointer: Color::Basic(1),
+			marker: Color::Basic(9),
+			broken: Color::Basic(9),
+			..Theme::dark()
+		}
+	}
+
+	/// A theme without any colours.
+	pub fn none() -> Self {
+		Theme::dark().with_depth(Depth::None)
+	}
+
+	/// Parse an fzf-style colour specification, a comma separated list of base
+	/// themes (`dark`, `light`, `16` or `bw`) and `part:color` pairs. Parts
+	/// are `fg`, `bg`, `bg+`, `hl`, `prompt`, `info`, `pointer`, `marker` and
+	/// `broken`. Later entries take precedence.
+	pub fn parse(spec: &str) -> Result<Theme, String> {
+		let mut theme = Theme::dark();
+		for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+			let (part, color) = match entry.find(':') {
+				Some(i) => (&entry[..i], Color::parse(&entry[i + 1..])?),
+				None => {
+					theme = match entry {
+						"dark" => Theme::dark(),
+						"light" => Theme::light(),
+						"16" => Theme::basic(),
+						"bw" => Theme::none(),
+						_ => return Err(format!("unknown color theme: {}", entry)),
+					};
+					continue;
+				}
+			};
+			let slot = match part {
+				"fg" => &mut theme.fg,
+				"bg" => &mut theme.bg,
+				"bg+" => &mut theme.bg_current,
+				"hl" => &mut theme.hl,
+				"prompt" => &mut theme.prompt,
+				"info" => &mut theme.info,
+				"pointer" => &mut theme.pointer,
+				"marker" => &mut theme.marker,
+				"broken" => &mut theme.broken,
+				_ => return Err(format!("unknown color part: {}", part)),
+			};
+			*slot = color;
+		}
+		Ok(theme)
+	}
+
+	/// Convert all colours to ones which a terminal of `depth` can show. The
+	/// depth of a theme can only be lowered.
+	pub fn with_depth(self, depth: Depth) -> Theme {
+		let depth = std::cmp::min(depth, self.depth);
+		Theme {
+			fg: self.fg.with_depth(depth),
+			bg: self.bg.with_depth(depth),
+			bg_current: self.bg_current.with_depth(depth),
+			hl: self.hl.with_depth(depth),
+			prompt: self.prompt.with_depth(depth),
+			info: self.info.with_depth(depth),
+			pointer: self.pointer.with_depth(depth),
+			marker: self.marker.with_depth(depth),
+			broken: self.broken.with_depth(depth),
+			depth,
+		}
+	}
+
+	/// Whether any colours are used.
+	pub fn has_color(&self) -> bool {
+		self.depth != Depth::None
+	}
+
+	/// How matches are highlighted. Without colours they are underlined.
+	pub fn highlight(&self) -> Highlight {
+		if self.has_color() {
+			Highlight {
+				start: self.hl.fg(),
+				end: "\u{1b}[39m".to_string(),
+			}
+		} else {
+			Highlight {
+				start: "\u{1b}[4m".to_string(),
+				end: "\u{1b}[24m".to_string(),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parsing_colors() {
+		assert_eq!(Color::parse("-1"), Ok(Color::Default));
+		assert_eq!(Color::parse("208"), Ok(Color::Indexed(208)));
+		assert_eq!(Color::parse("#ff8000"), Ok(Color::Rgb(255, 128, 0)));
+		assert_eq!(Color::parse("blue"), Ok(Color::Basic(4)));
+		assert_eq!(Color::parse("bright-red"), Ok(Color::Basic(9)));
+		assert!(Color::parse("256").is_err());
+		assert!(Color::parse("#ff80").is_err());
+		assert!(Color::parse("purple").is_err());
+	}
+
+	#[test]
+	fn escape_sequences() {
+		assert_eq!(Color::Default.fg(), "");
+		assert_eq!(Color::Basic(1).fg(), "\u{1b}[31m");
+		assert_eq!(Color::Basic(12).bg(), "\u{1b}[104m");
+		assert_eq!(Color::Indexed(12).fg(), "\u{1b}[38;5;12m");
+		assert_eq!(Color::Rgb(1, 2, 3).bg(), "\u{1b}[48;2;1;2;3m");
+	}
+
+	#[test]
+	fn reducing_depth() {
+		let grey = Color::Rgb(50, 50, 50);
+		assert_eq!(grey.with_depth(Depth::TrueColor), grey);
+		assert_eq!(grey.with_depth(Depth::Ansi256), Color::Indexed(236));
+		assert_eq!(grey.with_depth(Depth::Ansi16), Color::Basic(0));
+		assert_eq!(grey.with_depth(Depth::None), Color::Default);
+		assert_eq!(
+			Color::Rgb(255, 0, 0).with_depth(Depth::Ansi256),
+			Color::Indexed(196)
+		);
+		assert_eq!(
+			Color::Indexed(196).with_depth(Depth::Ansi16),
+			Color::Basic(9)
+		);
+		assert_eq!(Color::Indexed(4).with_depth(Depth::Ansi16), Color::Basic(4));
+	}
+
+	#[test]
+	fn detecting_depth() {
+		assert_eq!(Depth::from_env("1", "xterm-256color", ""), Depth::None);
+		assert_eq!(Depth::from_env("", "dumb", ""), Depth::None);
+		assert_eq!(Depth::from_env("", "xterm", "truecolor"), Depth::TrueColor);
+		assert_eq!(Depth::from_env("", "xterm-256color", ""), Depth::Ansi256);
+		assert_eq!(Depth::from_env("", "xterm", ""), Depth::Ansi16);
+	}
+
+	#[test]
+	fn parsing_themes() {
+		let theme = Theme::parse("light,hl:#ff0000,bg+:-1").unwrap();
+		assert_eq!(theme.hl, Color::Rgb(255, 0, 0));
+		assert_eq!(theme.bg_current, Color::Default);
+		assert_eq!(theme.info, Theme::light().info);
+
+		let theme = Theme::parse("hl:1,bw").unwrap();
+		assert!(!theme.has_color());
+		assert_eq!(theme.hl, Color::Default);
+
+		assert!(Theme::parse("nope").is_err());
+		assert!(Theme::parse("cursor:1").is_err());
+		assert!(Theme::parse("hl:nope").is_err());
+	}
+
+	#[test]
+	fn highlighting_without_color() {
+		let hl = Theme::none().highlight();
+		assert_eq!(hl.start, "\u{1b}[4m");
+		assert_eq!(Theme::dark().highlight().start, "\u{1b}[38;5;12m");
+	}
+}
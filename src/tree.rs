@@ -3,33 +3,54 @@
 // All files in the project carrying such notice may not be copied, modified, or
 // distributed except according to those terms.
 
-use crate::path::{create_paths, PathBehaviour, RcPath};
+use crate::columns::Columns;
+use crate::path::{aggregate_sizes, create_paths, ArcPath, Link};
+use crate::sort::{sort_paths, SortBy};
+use crate::style::{Guides, Style};
+use crate::text;
+use crate::theme::Highlight;
+use std::cmp;
 use std::io;
-use std::rc::Rc;
+use std::path::MAIN_SEPARATOR;
+use std::thread;
+use unicode_segmentation::UnicodeSegmentation;
 
-const DIR_OPEN: &str = "  ";
-const DIR_CLOSED: &str = "  ";
-const BLUE: &str = "\u{1b}[38;5;12m";
-const RESET: &str = "\u{1b}[39m";
 const COLOR_WRAP_LEN: usize = 15;
-const SELECTED: &str = "\u{1b}[38;5;9m>\u{1b}[39m";
+/// Number of paths matched between checks for cancellation.
+const CANCEL_CHECK_INTERVAL: usize = 1024;
 
 pub struct Tree {
-	pub paths: Vec<RcPath>,
-	pub tree: RcPath,
+	pub paths: Vec<ArcPath>,
+	pub tree: ArcPath,
 	pub n_paths: usize,
 	pub n_matches: usize,
 	pub n_selected: usize,
+	/// The text which the tree was last filtered by
+	pub query: String,
+	pub threads: usize,
+	/// Show chains of directories with a single child directory on one line
+	pub compact_dirs: bool,
+	pub sort_by: SortBy,
+	/// Whether the sort order is reversed
+	pub reverse: bool,
+	/// Metadata columns shown after each path
+	pub columns: Columns,
+	/// Recompute directory sizes over the matched files after filtering
+	pub du: bool,
+	pub style: Style,
 }
 
 impl Tree {
-	pub fn from_stdout(stdout: Vec<u8>) -> Result<Self, io::Error> {
-		let paths = create_paths(stdout)?;
+	/// Create a tree from the output of a `find`-like command. If `follow`,
+	/// symbolic links to directories are treated as directories.
+	pub fn from_stdout(stdout: Vec<u8>, follow: bool) -> Result<Self, io::Error> {
+		let paths = create_paths(stdout, follow)?;
 		Ok(Self::from_paths(paths))
 	}
 
-	pub fn from_paths(paths: Vec<RcPath>) -> Self {
-		let tree = link_paths(&paths);
+	/// Create a tree from linked `paths`, as created by `build_paths`.
+	pub fn from_paths(paths: Vec<ArcPath>) -> Self {
+		let tree = paths[0].clone();
 		let n_paths = paths.len();
 
 		Self {
@@ -38,15 +59,34 @@ impl Tree {
 			n_paths,
 			n_matches: n_paths,
 			n_selected: 0,
+			query: String::new(),
+			threads: 1,
+			compact_dirs: false,
+			sort_by: SortBy::default(),
+			reverse: false,
+			columns: Columns::default(),
+			du: false,
+			style: Style::default(),
 		}
 	}
 
+	/// Sort the children of every directory with the given order.
+	pub fn sort(&mut self, by: SortBy, reverse: bool) {
+		self.sort_by = by;
+		self.reverse = reverse;
+		self.paths = sort_paths(&self.tree, by, reverse);
+	}
+
+	/// Switch to the next sort order.
+	pub fn cycle_sort(&mut self) {
+		self.sort(self.sort_by.next(), self.reverse);
+	}
+
 	fn reset_matched(&self, value: bool) {
 		for path in &self.paths {
-			let basename = path.basename().to_string();
 			let mut pth = path.borrow_mut();
 			pth.matched = value;
-			pth.match_text = basename;
+			pth.match_text = None;
 		}
 	}
 
@@ -62,8 +102,35 @@ impl Tree {
 		}
 	}
 
+	/// Open all directories.
+	pub fn expand_all(&self) {
+		for path in &self.paths {
+			if path.borrow().is_dir {
+				path.borrow_mut().open = true;
+			}
+		}
+	}
+
+	/// Show at most `depth` levels below the root, collapsing the directories
+	/// any deeper.
+	pub fn collapse_below(&self, depth: usize) {
+		for path in &self.paths {
+			if path.borrow().is_dir && path.depth() > depth {
+				path.borrow_mut().open = false;
+			}
+		}
+	}
+
 	/// Filter all shown paths by matching with `text`.
 	pub fn filter(&mut self, text: &str) {
+		self.filter_until(text, &|| false);
+	}
+
+	/// Filter all shown paths by matching with `text`, abandoning the work as
+	/// soon as `cancelled` returns true. Returns whether filtering completed.
+	/// A cancelled filter leaves the tree partially matched, so it must be
+	/// filtered again before being displayed or indexed into.
+	pub fn filter_until(&mut self, text: &str, cancelled: &(dyn Fn() -> bool + Sync)) -> bool {
 		if text.is_empty() {
 			self.reset_matched(true);
 			self.n_matches = self.paths.len();
@@ -71,9 +138,42 @@ impl Tree {
 			self.reset_matched(false);
 			let patterns = split_by_space(text);
 			let patterns = reduce_patterns(&patterns);
-			match_paths(&self.paths, &patterns);
+			let hl = if self.style.plain {
+				Highlight::none()
+			} else {
+				self.style.theme.highlight()
+			};
+			if !match_paths(&self.paths, &patterns, self.threads, &hl, cancelled) {
+				return false;
+			}
 			self.n_matches = self.calc_n_matches();
 		}
+		if self.du {
+			self.update_sizes();
+		}
+		self.query = text.to_string();
+		true
+	}
+
+	/// The paths which matched the query themselves, rather than only
+	/// containing a match. Every path except the root matches an empty query.
+	pub fn matched_paths(&self) -> Vec<&ArcPath> {
+		self.paths[1..]
+			.iter()
+			.filter(|p| {
+				let pth = p.borrow();
+				pth.matched && (self.query.is_empty() || pth.match_text.is_some())
+			})
+			.collect()
+	}
+
+	/// Recompute directory sizes over the matched files, keeping siblings in
+	/// order when sorting by size.
+	pub fn update_sizes(&mut self) {
+		aggregate_sizes(&self.paths);
+		if self.sort_by == SortBy::Size {
+			self.sort(self.sort_by, self.reverse);
+		}
 	}
 
 	fn calc_n_matches(&self) -> usize {
@@ -85,22 +185,46 @@ impl Tree {
 	}
 
 	pub fn as_lines(&self) -> Vec<String> {
-		tree_string(&self.tree, self.n_matches)
+		tree_string(
+			&self.tree,
+			self.n_matches,
+			self.compact_dirs,
+			&self.columns,
+			&self.style,
+		)
+	}
+
+	/// The displayed lines, without columns, and the columns of each line,
+	/// which are left for the caller to lay out.
+	pub fn as_rows(&self) -> (Vec<String>, Vec<String>) {
+		tree_rows(
+			&self.tree,
+			self.n_matches,
+			self.compact_dirs,
+			&self.columns,
+			&self.style,
+		)
 	}
 
 	pub fn info_line(&self) -> String {
 		format!(
-			"(selected: {}, shown: {}, total: {})",
-			self.n_selected, self.n_matches, self.n_paths,
+			"(selected: {}, shown: {}, total: {}, sort: {}{})",
+			self.n_selected,
+			self.n_matches,
+			self.n_paths,
+			self.sort_by,
+			if self.reverse { " (reversed)" } else { "" },
 		)
 	}
 
 	/// Get the i'th visible path. Returns `None` if `target` is out of range.
-	fn ith(&self, mut target: usize) -> Option<&RcPath> {
+	/// When directories are compacted, a line is represented by the last path in
+	/// its chain.
+	pub fn ith(&self, mut target: usize) -> Option<&ArcPath> {
 		let mut i = 0;
 		loop {
 			let pth = self.paths.get(i)?;
-			if !pth.borrow().matched {
+			if !pth.borrow().matched || (self.compact_dirs && merged_child(pth).is_some()) {
 				target += 1;
 			}
 			if i == target {
@@ -122,6 +246,30 @@ impl Tree {
 		}
 	}
 
+	/// The columns of the `i`th displayed line taken up by the marker which
+	/// shows whether its directory is open. `None` if it isn't a directory.
+	pub fn marker_columns(&self, i: usize) -> Option<(usize, usize)> {
+		let pth = self.ith(i)?;
+		if !pth.borrow().is_dir {
+			return None;
+		}
+		// A line is indented once for each line above it in the hierarchy
+		let mut depth = 0;
+		let mut node = pth.borrow().parent.clone();
+		while let Some(ancestor) = node {
+			if !(self.compact_dirs && is_merged(&ancestor)) {
+				depth += 1;
+			}
+			node = ancestor.borrow().parent.clone();
+		}
+		if self.compact_dirs && is_merged(pth) {
+			depth -= 1;
+		}
+
+		let start = 1 + depth * text::width(&self.style.guides.branch);
+		Some((start, start + text::width(self.style.marker(&pth.borrow()))))
+	}
+
 	/// Flip the `selected` status of the `i`th displayed path.
 	pub fn flip_selected(&mut self, i: usize) {
 		{
@@ -147,39 +295,41 @@ impl Tree {
 	}
 }
 
-fn split_by_space(text: &str) -> Vec<&str> {
-	text.split(" ").filter(|x| !x.is_empty()).collect()
+impl Drop for Tree {
+	/// Paths hold references to both their parents and their children. Break
+	/// these cycles so that the paths can be freed.
+	fn drop(&mut self) {
+		for path in &self.paths {
+			let mut pth = path.borrow_mut();
+			pth.parent = None;
+			pth.children = None;
+		}
+	}
 }
 
-// TODO: Should be able to use node directly instead of a clone of the
-// joined path....
-fn push_seen(seen: &mut Vec<String>, node: &RcPath) -> bool {
-	let rf = &node.borrow().joined;
-	if seen.contains(rf) {
-		false
-	} else {
-		seen.push(rf.clone());
-		true
-	}
+fn split_by_space(text: &str) -> Vec<&str> {
+	text.split(' ').filter(|x| !x.is_empty()).collect()
 }
 
-// NB Since paths are assumed to be sorted, we assume that we'll iterate
-// children after parents
-fn match_stack(node: &RcPath, seen: &mut Vec<String>) -> usize {
-	let mut n = 1;
+/// Mark `node` and all of its ancestors as matched. Since an ancestor is only
+/// ever matched along with all of its own ancestors, the walk up the tree can
+/// stop at the first one which is already matched. This keeps marking linear
+/// in the number of paths, however many of them match.
+fn match_stack(node: &ArcPath) {
 	node.borrow_mut().matched = true;
-	push_seen(seen, &node);
+	let mut parent = node.borrow().parent.clone();
 
-	if let Some(parent) = &node.borrow().parent {
-		if push_seen(seen, &parent) {
-			n += match_stack(parent, seen);
+	while let Some(pth) = parent {
+		if pth.borrow().matched {
+			break;
 		}
+		pth.borrow_mut().matched = true;
+		parent = pth.borrow().parent.clone();
 	}
-	return n;
 }
 
 /// Reduce a vector of patterns to contain only elements which are disjoint
-fn reduce_patterns<'a>(patterns: &Vec<&'a str>) -> Vec<&'a str> {
+fn reduce_patterns<'a>(patterns: &[&'a str]) -> Vec<&'a str> {
 	let mut rm = Vec::new();
 
 	for (i, pat1) in patterns.iter().enumerate() {
@@ -204,7 +354,7 @@ fn reduce_patterns<'a>(patterns: &Vec<&'a str>) -> Vec<&'a str> {
 
 /// Check if `string` matches `patterns`. If `full`, then all patterns must
 /// be founed, otherwise a single pattern is enough.
-fn matches(string: &str, patterns: &Vec<&str>, full: bool) -> bool {
+fn matches(string: &str, patterns: &[&str], full: bool) -> bool {
 	if full {
 		patterns.iter().all(|pat| string.contains(pat))
 	} else {
@@ -218,7 +368,7 @@ struct MatchIdx {
 	end: usize,
 }
 
-fn match_indices(patterns: &Vec<&str>, string: &str) -> Vec<MatchIdx> {
+fn match_indices(patterns: &[&str], string: &str) -> Vec<MatchIdx> {
 	patterns
 		.iter()
 		.flat_map(|p| {
@@ -230,8 +380,30 @@ fn match_indices(patterns: &Vec<&str>, string: &str) -> Vec<MatchIdx> {
 		.collect()
 }
 
+/// Widen `idxs` to whole graphemes of `string`, so that highlighting never
+/// separates a character from its combining marks.
+fn snap_to_graphemes(string: &str, mut idxs: Vec<MatchIdx>) -> Vec<MatchIdx> {
+	if string.is_ascii() {
+		return idxs;
+	}
+	let bounds: Vec<usize> = string
+		.grapheme_indices(true)
+		.map(|(i, _)| i)
+		.chain(std::iter::once(string.len()))
+		.collect();
+	for idx in &mut idxs {
+		if let Err(i) = bounds.binary_search(&idx.start) {
+			idx.start = bounds[i - 1];
+		}
+		if let Err(i) = bounds.binary_search(&idx.end) {
+			idx.end = bounds[i];
+		}
+	}
+	idxs
+}
+
 fn merge_adjacent_indices(mut idxs: Vec<MatchIdx>) -> Vec<MatchIdx> {
-	if idxs.len() < 1 {
+	if idxs.is_empty() {
 		return idxs;
 	}
 	idxs.sort();
@@ -240,8 +412,8 @@ fn merge_adjacent_indices(mut idxs: Vec<MatchIdx>) -> Vec<MatchIdx> {
 		if i == idxs.len() {
 			return idxs;
 		}
-		if idxs[i - 1].end == idxs[i].start {
-			idxs[i - 1].end = idxs[i].end;
+		if idxs[i - 1].end >= idxs[i].start {
+			idxs[i - 1].end = cmp::max(idxs[i - 1].end, idxs[i].end);
 			idxs.remove(i);
 		} else {
 			i += 1;
@@ -249,7 +421,7 @@ fn merge_adjacent_indices(mut idxs: Vec<MatchIdx>) -> Vec<MatchIdx> {
 	}
 }
 
-fn wrap_matches_in_color(basename: &str, idxs: Vec<MatchIdx>) -> String {
+fn wrap_matches_in_color(basename: &str, idxs: Vec<MatchIdx>, hl: &Highlight) -> String {
 	if idxs.is_empty() {
 		basename.to_string()
 	} else {
@@ -257,11 +429,11 @@ fn wrap_matches_in_color(basename: &str, idxs: Vec<MatchIdx>) -> String {
 		let mut iter_idxs = idxs.into_iter();
 		let mut idx = iter_idxs.next().unwrap(); // We know idxs is not empty
 
-		for (j, c) in basename.chars().enumerate() {
+		for (j, c) in basename.char_indices() {
 			if j == idx.start {
-				text.push_str(BLUE);
+				text.push_str(&hl.start);
 			} else if j == idx.end {
-				text.push_str(RESET);
+				text.push_str(&hl.end);
 				if let Some(_idx) = iter_idxs.next() {
 					idx = _idx;
 				} else {
@@ -272,118 +444,148 @@ fn wrap_matches_in_color(basename: &str, idxs: Vec<MatchIdx>) -> String {
 			text.push(c);
 		}
 		if idx.end == basename.len() {
-			text.push_str(RESET);
+			text.push_str(&hl.end);
 		}
 		text
 	}
 }
 
-/// Works under the assumption that all patterns are disjoint. Use
-/// `reduce_patterns` to ensure this.
-fn match_paths(paths: &Vec<RcPath>, patterns: &Vec<&str>) {
+/// Find which of `paths` match `patterns`, returning the index of each match
+/// (offset by `start`) along with its colored match text. Returns `None` if
+/// `cancelled` becomes true before all paths are checked.
+fn match_chunk(
+	paths: &[ArcPath],
+	start: usize,
+	patterns: &[&str],
+	hl: &Highlight,
+	cancelled: &(dyn Fn() -> bool + Sync),
+) -> Option<Vec<(usize, String)>> {
 	// TODO: Abstract a match function with a trait bound (use this in
 	// reduce_patterns too)
-	let mut seen = Vec::new();
+	let mut matched = Vec::new();
+	// Paths are in display order, so the parent of each path is one of the
+	// directories above the previous one. Their joined paths are prefixes of
+	// `joined`, each kept with its length
+	let mut dirs: Vec<(ArcPath, usize)> = Vec::new();
+	let mut joined = String::new();
+
+	for (i, path) in paths.iter().enumerate() {
+		if i % CANCEL_CHECK_INTERVAL == 0 && cancelled() {
+			return None;
+		}
 
-	for path in paths {
-		if matches(&path.borrow().joined, patterns, true) {
-			let basename = &path.basename();
-			let mut idxs = match_indices(patterns, basename);
-			idxs = merge_adjacent_indices(idxs);
-			let text = wrap_matches_in_color(basename, idxs);
-			match_stack(path, &mut seen);
-			path.borrow_mut().match_text = text;
+		let pth = path.borrow();
+		match &pth.parent {
+			Some(parent) => {
+				while dirs.last().is_some_and(|(d, _)| !d.ptr_eq(parent)) {
+					dirs.pop();
+				}
+				match dirs.last() {
+					Some(&(_, len)) => joined.truncate(len),
+					None => {
+						// The chunk started below the parent
+						joined = if parent.is_root() {
+							parent.prefix().to_string()
+						} else {
+							parent.joined()
+						};
+						dirs.push((parent.clone(), joined.len()));
+					}
+				}
+				if !joined.is_empty() && !joined.ends_with(MAIN_SEPARATOR) {
+					joined.push(MAIN_SEPARATOR);
+				}
+				joined.push_str(&pth.name);
+			}
+			None => {
+				dirs.clear();
+				joined.clear();
+				joined.push_str(&pth.name);
+			}
+		}
+		let is_dir = pth.is_dir;
+		drop(pth);
+
+		if matches(&joined, patterns, true) {
+			let basename = path.basename();
+			let mut idxs = match_indices(patterns, &basename);
+			idxs = merge_adjacent_indices(snap_to_graphemes(&basename, idxs));
+			matched.push((start + i, wrap_matches_in_color(&basename, idxs, hl)));
 		}
-	}
-}
 
-fn peek(stack: &[RcPath], i: usize) -> Option<&RcPath> {
-	if i < stack.len() {
-		return Some(&stack[i]);
+		if is_dir {
+			if path.is_root() {
+				// The root's name isn't part of its descendants' paths
+				joined.clear();
+				joined.push_str(path.prefix());
+			}
+			dirs.push((path.clone(), joined.len()));
+		}
 	}
-	None
+	Some(matched)
 }
 
-macro_rules! debug_relation {
-	($obj1:expr; child $obj2:expr) => {
-		trace!("{:?} is child of {:?}", $obj1.borrow(), $obj2.borrow());
-	};
-	($obj1:expr; unrelated $obj2:expr, $obj3:expr) => {
-		trace!(
-			"{:?} is unrelated to {:?} and {:?}",
-			$obj1.borrow(),
-			$obj2.borrow(),
-			$obj3.borrow()
-		);
-	};
-}
-
-/// Inner recursive function for creating a directory tree.
+/// Works under the assumption that all patterns are disjoint. Use
+/// `reduce_patterns` to ensure this.
 ///
-/// Max recursion depth will be equal to the max directory depth. It is
-/// unlikely that we'll hit a stack overflow.
+/// Matching is split into contiguous chunks across `threads` workers. Results
+/// are merged in path order so that the outcome is independent of the number
+/// of threads used.
 ///
-/// There are three potential branches in each recursion frame:
-///  1. If `next` is child of `prev`: add `next` to `prev` and recurse with
-///     `prev` as `base`, `next` as `prev`
-///  2. If `next` is a child of `base`: consume `next` and add it to `base`
-///     then loop
-///  3. In all other cases: break with `i` and move up one recursion frame
-fn _create_tree<'a>(
-	mut i: usize,
-	base: &RcPath,
-	mut prev: Option<&'a RcPath>,
-	stack: &'a [RcPath],
-) -> usize {
-	loop {
-		trace!("~~~ {} ~~~", i);
-		i += 1;
-		if let Some(next) = peek(stack, i) {
-			if let Some(prev) = prev {
-				if next.is_child_of(&prev) {
-					debug_relation!(next; child base);
-					prev.add_child(&next);
-					i = _create_tree(i, &prev, Some(&next), stack) - 1;
-				} else if next.is_child_of(&base) {
-					debug_relation!(next; child base);
-					base.add_child(&next);
-				} else {
-					debug_relation!(next; unrelated base, prev);
-					break i;
-				}
-			} else if next.is_child_of(&base) {
-				debug_relation!(next; child base);
-				base.add_child(&next);
-			} else {
-				// Given that we always include all directories, there is never a broken
-				// link between `base` and `next` if `prev` is `None`. This means that
-				// this code is unreachable. It's left here in case the directory
-				// discovery changes in the future.
-				break i;
+/// Returns false, without marking any path as matched, if `cancelled` becomes
+/// true while matching.
+fn match_paths(
+	paths: &[ArcPath],
+	patterns: &[&str],
+	threads: usize,
+	hl: &Highlight,
+	cancelled: &(dyn Fn() -> bool + Sync),
+) -> bool {
+	let chunk_size = cmp::max(1, (paths.len() + threads - 1) / cmp::max(1, threads));
+
+	let matched: Option<Vec<(usize, String)>> = if threads <= 1 || paths.len() <= chunk_size {
+		match_chunk(paths, 0, patterns, hl, cancelled)
+	} else {
+		thread::scope(|s| {
+			let handles: Vec<_> = paths
+				.chunks(chunk_size)
+				.enumerate()
+				.map(|(i, chunk)| {
+					s.spawn(move || match_chunk(chunk, i * chunk_size, patterns, hl, cancelled))
+				})
+				.collect();
+			let mut matched = Some(Vec::new());
+			for h in handles {
+				let chunk = h.join().expect("matching thread panicked");
+				matched = matched.and_then(|mut m: Vec<_>| {
+					m.extend(chunk?);
+					Some(m)
+				});
 			}
-			prev = Some(next);
-		} else {
-			break i;
-		}
-	}
-}
+			matched
+		})
+	};
 
-/// Create relationships between all nodes in the directory structure for
-/// `paths`.
-pub fn link_paths(paths: &Vec<RcPath>) -> RcPath {
-	_create_tree(0, &paths[0], None, &paths[..]);
-	Rc::clone(&paths[0])
+	let matched = match matched {
+		Some(m) => m,
+		None => return false,
+	};
+
+	for (i, text) in matched {
+		match_stack(&paths[i]);
+		paths[i].borrow_mut().match_text = Some(text);
+	}
+	true
 }
 
 #[derive(Clone)]
 enum Segment {
-	Continuation, // "│   " up to basename, "├── " at basename
-	End,          // "    " up to basename, "└── " at basename
+	Continuation, // e.g. "│   " up to basename, "├── " at basename
+	End,          // e.g. "    " up to basename, "└── " at basename
 }
 
-fn segments_to_string(segments: &Vec<Segment>) -> String {
-	// Each char is 4 bytes, each string representation is 4 chars
-	let mut s = String::with_capacity(4 * 4 * segments.len());
+fn segments_to_string(segments: &[Segment], guides: &Guides) -> String {
+	let mut s = String::with_capacity(4 * guides.end.len() * segments.len());
 
 	if segments.is_empty() {
 		return s;
@@ -391,42 +593,106 @@ fn segments_to_string(segments: &Vec<Segment>) -> String {
 
 	for seg in segments[..segments.len() - 1].iter() {
 		s.push_str(match seg {
-			Segment::Continuation => "│   ",
-			Segment::End => "    ",
+			Segment::Continuation => &guides.continuation,
+			Segment::End => &guides.end,
 		});
 	}
 	s.push_str(match segments[segments.len() - 1] {
-		Segment::Continuation => "├── ",
-		Segment::End => "└── ",
+		Segment::Continuation => &guides.branch,
+		Segment::End => &guides.last,
 	});
 	s
 }
 
+/// If `node` is shown on the same line as its only child directory when
+/// compacting directories, return that child. The root is never compacted,
+/// and a chain stops at a link so that its target is shown beside it.
+fn merged_child(node: &ArcPath) -> Option<ArcPath> {
+	let pth = node.borrow();
+	if pth.parent.is_none() || !pth.is_dir || !pth.open || pth.link.is_some() {
+		return None;
+	}
+	let mut children = pth.children.as_ref()?.iter().filter(|c| c.borrow().matched);
+	let child = children.next()?;
+	if children.next().is_some() || !child.borrow().is_dir {
+		return None;
+	}
+	Some(child.clone())
+}
+
+/// Whether `node` is shown on its parent's line when compacting directories.
+fn is_merged(node: &ArcPath) -> bool {
+	let parent = node.borrow().parent.clone();
+	parent
+		.and_then(|p| merged_child(&p))
+		.is_some_and(|child| child.ptr_eq(node))
+}
+
+fn display_name(node: &ArcPath) -> String {
+	let pth = node.borrow();
+	match &pth.match_text {
+		Some(text) => text.clone(),
+		None => pth.name.to_string(),
+	}
+}
+
+/// The ` -> target` shown after a symbolic link, coloured if the link is
+/// broken.
+fn link_string(link: &Link, style: &Style) -> String {
+	let broken = style.theme.broken;
+	if link.broken {
+		format!(" {}-> {}{}", broken.fg(), link.target, broken.fg_reset())
+	} else {
+		format!(" -> {}", link.target)
+	}
+}
+
 /// Inner recursive function to create a string representation of a directory
 /// tree.
-fn _tree_string(node: &RcPath, lines: &mut Vec<String>, segments: Vec<Segment>) {
+fn _tree_string(
+	node: &ArcPath,
+	lines: &mut Vec<String>,
+	cols: &mut Vec<String>,
+	segments: Vec<Segment>,
+	compact: bool,
+	columns: &Columns,
+	style: &Style,
+) {
+	let mut name = display_name(node);
+	let mut node = node.clone();
+	if compact {
+		while let Some(child) = merged_child(&node) {
+			name.push(MAIN_SEPARATOR);
+			name.push_str(&display_name(&child));
+			node = child;
+		}
+	}
+
+	let mut name = style.paint(&node.borrow(), &name);
+	if let Some(link) = &node.borrow().link {
+		name.push_str(&link_string(link, style));
+	}
+
 	let sel = if node.borrow().selected {
-		&SELECTED
+		format!(
+			"{}>{}",
+			style.theme.marker.fg(),
+			style.theme.marker.fg_reset()
+		)
 	} else {
-		" "
+		" ".to_string()
 	};
 
-	let prefix = if node.borrow().is_dir {
-		if node.borrow().open {
-			DIR_OPEN
-		} else {
-			DIR_CLOSED
-		}
-	} else {
-		""
-	};
+	let prefix = style.marker(&node.borrow());
 
-	lines
-		.push(sel.to_owned() + &segments_to_string(&segments) + prefix + &node.borrow().match_text);
+	lines.push(sel + &segments_to_string(&segments, &style.guides) + prefix + &name);
+	if columns.is_shown() {
+		cols.push(columns.format(&node.borrow()));
+	}
 
 	if node.borrow().open {
 		if let Some(children) = &node.borrow().children {
-			let children: Vec<&RcPath> = children.iter().filter(|x| x.borrow().matched).collect();
+			let children: Vec<&ArcPath> = children.iter().filter(|x| x.borrow().matched).collect();
 			for (i, child) in children.iter().enumerate() {
 				let mut segments = segments.clone();
 
@@ -435,7 +701,7 @@ fn _tree_string(node: &RcPath, lines: &mut Vec<String>, segments: Vec<Segment>)
 				} else {
 					Segment::Continuation
 				});
-				_tree_string(child, lines, segments);
+				_tree_string(child, lines, cols, segments, compact, columns, style);
 			}
 		}
 	}
@@ -443,21 +709,76 @@ fn _tree_string(node: &RcPath, lines: &mut Vec<String>, segments: Vec<Segment>)
 
 /// Create a vec of strings representing the directory tree `tree`. We can
 /// preallocate the exact capacity by knowing the number of paths we are
-/// constructing for.
-pub fn tree_string(tree: &RcPath, len: usize) -> Vec<String> {
+/// constructing for. If `compact`, chains of directories which each contain
+/// a single directory are shown on one line. Any shown `columns` are lined
+/// up on the right of the widest line, and names are decorated with `style`.
+pub fn tree_string(
+	tree: &ArcPath,
+	len: usize,
+	compact: bool,
+	columns: &Columns,
+	style: &Style,
+) -> Vec<String> {
+	let (mut lines, cols) = tree_rows(tree, len, compact, columns, style);
+	if !cols.is_empty() {
+		let widths: Vec<usize> = lines.iter().map(|l| text::width(l)).collect();
+		let max_width = widths.iter().copied().max().unwrap_or(0);
+		for ((line, width), col) in lines.iter_mut().zip(widths).zip(cols) {
+			line.push_str(&" ".repeat(max_width - width));
+			line.push_str(&col);
+		}
+	}
+	lines
+}
+
+/// As `tree_string`, but with the shown `columns` of each line given
+/// separately, without trailing spaces. They are empty if none are shown.
+pub fn tree_rows(
+	tree: &ArcPath,
+	len: usize,
+	compact: bool,
+	columns: &Columns,
+	style: &Style,
+) -> (Vec<String>, Vec<String>) {
 	let mut lines = Vec::with_capacity(len);
+	let mut cols = Vec::new();
 	if len > 0 {
-		_tree_string(tree, &mut lines, Vec::new());
+		_tree_string(
+			tree,
+			&mut lines,
+			&mut cols,
+			Vec::new(),
+			compact,
+			columns,
+			style,
+		);
 	}
-	lines
+
+	for col in &mut cols {
+		col.truncate(col.trim_end().len());
+	}
+	(lines, cols)
 }
 
 #[cfg(test)]
 mod test {
 	use super::*;
-	use crate::path::{self, Path};
+	use crate::path::{build_paths, Meta, Path};
+	use crate::style::{ASCII_CLOSED, ASCII_OPEN, DIR_CLOSED, DIR_OPEN};
+
+	const BLUE: &str = "\u{1b}[38;5;12m";
+	const RESET: &str = "\u{1b}[39m";
+	const RED: &str = "\u{1b}[38;5;9m";
+
+	/// Decorate names with icons, as the tree is shown with `--icons=always`
+	fn icons() -> Style {
+		Style {
+			icons: true,
+			..Style::default()
+		}
+	}
 
-	fn create_test_paths() -> Vec<RcPath> {
+	fn create_test_paths() -> Vec<ArcPath> {
 		paths![
 			".",
 			"./A",
@@ -473,106 +794,157 @@ mod test {
 		]
 	}
 
-	fn create_test_tree(paths: &Vec<RcPath>) -> RcPath {
-		let root = Rc::clone(&paths[0]);
-		root.add_child(&paths[1]);
-		root.add_child(&paths[2]);
-		root.add_child(&paths[3]);
-		let _ = &paths[3].add_child(&paths[4]);
-		let _ = &paths[4].add_child(&paths[5]);
-		let _ = &paths[4].add_child(&paths[6]);
-		let _ = &paths[3].add_child(&paths[7]);
-		let _ = &paths[7].add_child(&paths[8]);
-		let _ = &paths[7].add_child(&paths[9]);
-		root.add_child(&paths[10]);
-		root
-	}
-
-	/// Determine whether two trees are equal by recursing into all branches
-	fn trees_equal(a: &RcPath, b: &RcPath) -> bool {
-		if a != b {
-			return false;
-		}
-
-		if let Some(a_children) = &a.borrow().children {
-			if let Some(b_children) = &b.borrow().children {
-				if a_children.len() != b_children.len() {
-					return false;
-				}
-				for (aa, bb) in a_children.iter().zip(b_children.iter()) {
-					if !trees_equal(aa, bb) {
-						return false;
-					}
-				}
-			} else {
-				return false;
-			}
-		}
-
-		true
-	}
-
 	#[test]
 	fn create_tree_correct() {
 		let paths = create_test_paths();
-		let tree = link_paths(&paths);
-		let tree2 = create_test_tree(&paths);
-		assert!(trees_equal(&tree, &tree2));
+		let parents: Vec<Option<usize>> = paths
+			.iter()
+			.map(|p| {
+				let parent = p.borrow().parent.clone()?;
+				paths.iter().position(|q| q.ptr_eq(&parent))
+			})
+			.collect();
+		let expected = vec![
+			None,
+			Some(0),
+			Some(0),
+			Some(0),
+			Some(3),
+			Some(4),
+			Some(4),
+			Some(3),
+			Some(7),
+			Some(7),
+			Some(0),
+		];
+		assert_eq!(parents, expected);
 	}
 
 	#[test]
 	fn tree_string_correct() {
 		let paths = create_test_paths();
-		let tree = link_paths(&paths);
-		let lines = tree_string(&tree, paths.len());
+		let tree = paths[0].clone();
+		let lines = tree_string(&tree, paths.len(), false, &Columns::default(), &icons());
 		let expected = vec![
 			"   .",
 			" ├──   A",
 			" ├──   B",
 			" ├──   src",
 			" │   ├──   bayes",
-			" │   │   ├── blend.c",
-			" │   │   └── rand.c",
+			" │   │   ├──   blend.c",
+			" │   │   └──   rand.c",
 			" │   └──   cakes",
-			" │       ├── a.c",
-			" │       └── b.c",
-			" └── x.txt",
+			" │       ├──   a.c",
+			" │       └──   b.c",
+			" └──   x.txt",
 		];
 		assert_eq!(lines, expected);
 
 		// Deselect `./src/bayes` and print again
 		paths[4].borrow_mut().matched = false;
-		let lines = tree_string(&tree, paths.len());
+		let lines = tree_string(&tree, paths.len(), false, &Columns::default(), &icons());
 		let expected = vec![
 			"   .",
 			" ├──   A",
 			" ├──   B",
 			" ├──   src",
 			" │   └──   cakes",
-			" │       ├── a.c",
-			" │       └── b.c",
-			" └── x.txt",
+			" │       ├──   a.c",
+			" │       └──   b.c",
+			" └──   x.txt",
 		];
 		assert_eq!(lines, expected);
 	}
 
+	#[test]
+	fn finding_directory_markers() {
+		let tree = Tree::from_paths(create_test_paths());
+		// After the selection marker and two levels of guides
+		assert_eq!(tree.marker_columns(4), Some((9, 11)));
+		assert_eq!(tree.marker_columns(0), Some((1, 3)));
+		assert_eq!(tree.marker_columns(5), None);
+
+		let paths = paths![
+			"./src",
+			"./src/main",
+			"./src/main/java",
+			"./src/main/java/App.java",
+			"./src/main/java/util",
+			"./src/main/java/util/Lib.java",
+			"./x.txt"
+		];
+		let mut tree = Tree::from_paths(paths);
+		tree.compact_dirs = true;
+		let lines = tree.as_lines();
+		assert_eq!(lines[1], format!(" ├── {}src/main/java", ASCII_OPEN));
+		assert_eq!(tree.marker_columns(1), Some((5, 7)));
+		assert_eq!(lines[3], format!(" │   └── {}util", ASCII_OPEN));
+		assert_eq!(tree.marker_columns(3), Some((9, 11)));
+	}
+
+	#[test]
+	fn compacted_directories_share_a_line() {
+		let paths = paths![
+			"./src",
+			"./src/main",
+			"./src/main/java",
+			"./src/main/java/App.java",
+			"./src/main/java/Lib.java",
+			"./x.txt"
+		];
+		let mut tree = Tree::from_paths(paths);
+		tree.compact_dirs = true;
+		tree.style = icons();
+		let expected = vec![
+			format!(" {}.", DIR_OPEN),
+			format!(" ├── {}src/main/java", DIR_OPEN),
+			" │   ├──   App.java".to_string(),
+			" │   └──   Lib.java".to_string(),
+			" └──   x.txt".to_string(),
+		];
+		assert_eq!(tree.as_lines(), expected);
+		assert!(tree.ith(1).unwrap().ptr_eq(&tree.paths[3]));
+		assert!(tree.ith(2).unwrap().ptr_eq(&tree.paths[4]));
+		assert!(tree.ith(4).unwrap().ptr_eq(&tree.paths[6]));
+
+		tree.flip_open(1);
+		let expected = vec![
+			format!(" {}.", DIR_OPEN),
+			format!(" ├── {}src/main/java", DIR_CLOSED),
+			" └──   x.txt".to_string(),
+		];
+		assert_eq!(tree.as_lines(), expected);
+		assert!(tree.ith(2).unwrap().ptr_eq(&tree.paths[6]));
+
+		tree.flip_open(1);
+		tree.filter("main");
+		let expected = vec![
+			format!(" {}.", DIR_OPEN),
+			format!(" └── {}src/{}main{}/java", DIR_OPEN, BLUE, RESET),
+			"     ├──   App.java".to_string(),
+			"     └──   Lib.java".to_string(),
+		];
+		assert_eq!(tree.as_lines(), expected);
+	}
+
 	#[test]
 	fn correct_lines_after_filtering() {
 		let paths = create_test_paths();
 		let mut tree = Tree::from_paths(paths);
+		tree.style = icons();
 		tree.filter("b");
 		let lines = tree.as_lines();
-		let colored = vec![
+		let colored = [
 			format!("     ├──   {}b{}ayes", BLUE, RESET),
-			format!("     │   ├── {}b{}lend.c", BLUE, RESET),
-			format!("         └── {}b{}.c", BLUE, RESET),
+			format!("     │   ├──   {}b{}lend.c", BLUE, RESET),
+			format!("         └──   {}b{}.c", BLUE, RESET),
 		];
 		let expected = vec![
 			"   .",
 			" └──   src",
 			&colored[0],
 			&colored[1],
-			"     │   └── rand.c",
+			"     │   └──   rand.c",
 			"     └──   cakes",
 			&colored[2],
 		];
@@ -618,36 +990,260 @@ mod test {
 		assert_eq!(response, expected);
 	}
 
+	#[test]
+	fn sorted_paths_follow_display_order() {
+		let mut tree = Tree::from_paths(create_test_paths());
+		tree.sort(SortBy::Name, true);
+		let names: Vec<String> = tree.paths.iter().map(|p| p.joined()).collect();
+		assert_eq!(
+			names,
+			vec![
+				".",
+				"./x.txt",
+				"./src",
+				"./src/cakes",
+				"./src/cakes/b.c",
+				"./src/cakes/a.c",
+				"./src/bayes",
+				"./src/bayes/rand.c",
+				"./src/bayes/blend.c",
+				"./B",
+				"./A"
+			]
+		);
+
+		tree.flip_selected(1);
+		assert!(tree.paths[1].borrow().selected);
+		assert!(tree.info_line().ends_with("sort: name (reversed))"));
+	}
+
+	#[test]
+	fn du_sizes_follow_matches() {
+		let file = |size| Meta {
+			size,
+			..Meta::default()
+		};
+		let paths = build_paths(vec![
+			("a/x.c", file(1)),
+			("a/y.rs", file(2)),
+			("b/z.rs", file(4)),
+			("b/w.c", file(8)),
+		]);
+		let mut tree = Tree::from_paths(paths);
+		tree.du = true;
+		tree.sort(SortBy::Size, false);
+		assert_eq!(tree.paths[1].joined(), "b");
+		assert_eq!(tree.tree.borrow().total_size, 15);
+
+		tree.filter(".rs");
+		let totals: Vec<(String, u64)> = tree
+			.paths
+			.iter()
+			.filter(|p| p.borrow().matched)
+			.map(|p| (p.joined(), p.borrow().total_size))
+			.collect();
+		assert_eq!(
+			totals,
+			vec![
+				(".".to_string(), 6),
+				("b".to_string(), 4),
+				("b/z.rs".to_string(), 4),
+				("a".to_string(), 2),
+				("a/y.rs".to_string(), 2),
+			]
+		);
+
+		tree.filter(".c");
+		assert_eq!(tree.paths[1].joined(), "b");
+		assert_eq!(tree.paths[1].borrow().total_size, 8);
+		tree.filter("x.c");
+		assert_eq!(tree.paths[1].joined(), "a");
+	}
+
+	#[test]
+	fn links_show_their_targets() {
+		let link = |target: &str, broken| Meta {
+			link: Some(Link {
+				target: target.into(),
+				broken,
+			}),
+			..Meta::default()
+		};
+		let paths = build_paths(vec![("a", link("b", false)), ("c", link("d", true))]);
+		let tree = Tree::from_paths(paths);
+		let lines = tree.as_lines();
+		assert_eq!(lines[1], " ├── a -> b");
+		assert_eq!(lines[2], format!(" └── c {}-> d{}", RED, RESET));
+	}
+
+	#[test]
+	fn compacted_chains_stop_at_links() {
+		let dir = |link: Option<&str>| Meta {
+			is_dir: true,
+			link: link.map(|target| Link {
+				target: target.into(),
+				broken: false,
+			}),
+			..Meta::default()
+		};
+		let paths = build_paths(vec![
+			("a", dir(None)),
+			("a/b", dir(Some("../x"))),
+			("a/b/c", dir(Some("../y"))),
+			("a/b/c/d", Meta::default()),
+		]);
+		let mut tree = Tree::from_paths(paths);
+		tree.compact_dirs = true;
+		assert_eq!(
+			tree.as_lines(),
+			vec![
+				format!(" {}.", ASCII_OPEN),
+				format!(" └── {}a/b -> ../x", ASCII_OPEN),
+				format!("     └── {}c -> ../y", ASCII_OPEN),
+				"         └── d".to_string(),
+			]
+		);
+	}
+
+	#[test]
+	fn columns_are_aligned_after_the_tree() {
+		let file = |size| Meta {
+			size,
+			..Meta::default()
+		};
+		let paths = build_paths(vec![("a/long-name", file(1)), ("b", file(2048))]);
+		let mut tree = Tree::from_paths(paths);
+		tree.columns = Columns {
+			size: true,
+			shown: true,
+			..Columns::default()
+		};
+		assert_eq!(
+			tree.as_lines(),
+			vec![
+				" - .                   -",
+				" ├── - a               -",
+				" │   └── long-name     1",
+				" └── b              2.0K",
+			]
+		);
+		assert_eq!(tree.marker_columns(1), Some((5, 7)));
+
+		tree.columns.toggle();
+		assert_eq!(tree.as_lines()[3], " └── b");
+	}
+
+	#[test]
+	fn matching_only_the_root_matches_nothing() {
+		let mut tree = Tree::from_paths(paths!["a/b", "c"]);
+		tree.filter(".");
+		assert!(tree.paths[0].borrow().matched);
+		assert!(tree.matched_paths().is_empty());
+	}
+
+	#[test]
+	fn matched_paths_exclude_ancestors() {
+		let mut tree = Tree::from_paths(create_test_paths());
+		let joined = |tree: &Tree| -> Vec<String> {
+			tree.matched_paths().iter().map(|p| p.joined()).collect()
+		};
+		assert_eq!(joined(&tree).len(), 10);
+
+		tree.filter("b");
+		assert_eq!(
+			joined(&tree),
+			vec![
+				"./src/bayes",
+				"./src/bayes/blend.c",
+				"./src/bayes/rand.c",
+				"./src/cakes/b.c"
+			]
+		);
+
+		tree.filter("nothing");
+		assert!(joined(&tree).is_empty());
+	}
+
+	#[test]
+	fn collapsing_below_a_depth() {
+		let tree = Tree::from_paths(create_test_paths());
+		tree.collapse_below(1);
+		let lines = tree.as_lines();
+		assert_eq!(lines.len(), 5);
+		assert_eq!(lines[3], format!(" ├── {}src", ASCII_CLOSED));
+	}
+
+	#[test]
+	fn collapsing_shows_n_levels_below_the_root() {
+		let tree = Tree::from_paths(create_test_paths());
+		let shown = |tree: &Tree| -> Vec<String> {
+			(0..)
+				.map_while(|i| tree.ith(i))
+				.map(|p| p.joined())
+				.collect()
+		};
+		tree.collapse_below(1);
+		assert_eq!(shown(&tree), vec![".", "./A", "./B", "./src", "./x.txt"]);
+
+		tree.expand_all();
+		tree.collapse_below(2);
+		assert_eq!(
+			shown(&tree),
+			vec![
+				".",
+				"./A",
+				"./B",
+				"./src",
+				"./src/bayes",
+				"./src/cakes",
+				"./x.txt"
+			]
+		);
+		assert!(!tree.paths[4].borrow().open);
+		assert!(!tree.paths[7].borrow().open);
+	}
+
+	#[test]
+	fn expanding_all_directories() {
+		let tree = Tree::from_paths(create_test_paths());
+		tree.collapse_below(1);
+		tree.expand_all();
+		assert_eq!(tree.as_lines().len(), 11);
+	}
+
 	#[test]
 	fn correct_n_descendants() {
 		let paths = create_test_paths();
-		let tree = link_paths(&paths);
-		assert_eq!(tree.n_descendants(), 10);
+		assert_eq!(paths[0].n_descendants(), 10);
 		assert_eq!(paths[3].n_descendants(), 6);
 	}
 
 	#[test]
 	fn reducing_patterns() {
-		assert_eq!(reduce_patterns(&vec!["abc", "def"]), vec!["abc", "def"]);
-		assert_eq!(reduce_patterns(&vec!["abc", "abc"]), vec!["abc"]);
-		assert_eq!(reduce_patterns(&vec!["aaa", "aaaa", "a"]), vec!["aaaa"]);
-		assert_eq!(
-			reduce_patterns(&vec!["apa", "aaaa", "a"]),
-			vec!["aaaa", "apa"]
-		);
+		assert_eq!(reduce_patterns(&["abc", "def"]), vec!["abc", "def"]);
+		assert_eq!(reduce_patterns(&["abc", "abc"]), vec!["abc"]);
+		assert_eq!(reduce_patterns(&["aaa", "aaaa", "a"]), vec!["aaaa"]);
+		assert_eq!(reduce_patterns(&["apa", "aaaa", "a"]), vec!["aaaa", "apa"]);
 	}
 
 	#[test]
 	fn match_paths_sets_matched_field_correctly() {
+		let path = |name: &str| Path::new(name.into(), Meta::default());
 		let paths = vec![
-			path::Path::new("this/is/aaaa/paath.txt".to_string(), false),
-			path::Path::new("this/is/aaaa/paath.txt".to_string(), false),
-			path::Path::new("this/is/aaaa/file.ext".to_string(), false),
+			path("this/is/aaaa/paath.txt"),
+			path("this/is/aaaa/paath.txt"),
+			path("this/is/aaaa/file.ext"),
 		];
 		for p in &paths {
 			p.borrow_mut().matched = false;
 		}
-		match_paths(&paths, &vec!["aaaa", "this", "paath.txt"]);
+		match_paths(
+			&paths,
+			&["aaaa", "this", "paath.txt"],
+			1,
+			&Highlight::default(),
+			&|| false,
+		);
 		assert!(paths[0].borrow().matched);
 		assert!(paths[1].borrow().matched);
 		assert!(!paths[2].borrow().matched);
@@ -656,28 +1252,107 @@ mod test {
 	}
 
 	#[test]
-	fn match_paths_colors_basename() {
-		let paths = vec![
-			path::Path::new("this/is/file.rs".to_string(), false),
-			path::Path::new("this/is/fxiyle.xrs".to_string(), false),
+	fn matching_deduplicated_paths() {
+		let paths = paths![
+			"this/is/aaaa/paath.txt",
+			"this/is/aaaa/paath.txt",
+			"this/is/aaaa/file.ext"
 		];
+		for p in &paths {
+			p.borrow_mut().matched = false;
+		}
+		match_paths(
+			&paths,
+			&["aaaa", "this", "paath.txt"],
+			1,
+			&Highlight::default(),
+			&|| false,
+		);
+		// The duplicated name is kept once, below the directories created for
+		// it, which are counted as matches along with the root
+		let matched: Vec<(String, bool)> = paths
+			.iter()
+			.map(|p| (p.joined(), p.borrow().matched))
+			.collect();
+		assert_eq!(
+			matched,
+			vec![
+				(".".to_string(), true),
+				("this".to_string(), true),
+				("this/is".to_string(), true),
+				("this/is/aaaa".to_string(), true),
+				("this/is/aaaa/file.ext".to_string(), false),
+				("this/is/aaaa/paath.txt".to_string(), true),
+			]
+		);
+
+		assert_eq!(Tree::from_paths(paths).calc_n_matches(), 5);
+	}
+
+	#[test]
+	fn match_stack_marks_ancestors_once() {
+		let paths = create_test_paths();
+		let tree = Tree::from_paths(paths);
+		tree.reset_matched(false);
+
+		match_stack(&tree.paths[5]);
+		match_stack(&tree.paths[8]);
 
-		match_paths(&paths, &vec!["file.rs"]);
+		let matched: Vec<usize> = (0..tree.paths.len())
+			.filter(|&i| tree.paths[i].borrow().matched)
+			.collect();
+		assert_eq!(matched, vec![0, 3, 4, 5, 7, 8]);
+	}
+
+	#[test]
+	fn match_paths_colors_basename() {
+		let paths = paths!["this/is/file.rs", "this/is/fxiyle.xrs"];
+
+		match_paths(&paths, &["file.rs"], 1, &Highlight::default(), &|| false);
 		assert_eq!(
-			paths[0].borrow().match_text,
-			format!("{}file.rs{}", BLUE, RESET)
+			paths[3].borrow().match_text,
+			Some(format!("{}file.rs{}", BLUE, RESET))
 		);
 
-		match_paths(&paths, &vec!["x", "y"]);
+		match_paths(&paths, &["x", "y"], 1, &Highlight::default(), &|| false);
 		assert_eq!(
-			paths[1].borrow().match_text,
-			format!(
+			paths[4].borrow().match_text,
+			Some(format!(
 				"f{}x{}i{}y{}le.{}x{}rs",
 				BLUE, RESET, BLUE, RESET, BLUE, RESET
-			)
+			))
 		);
 	}
 
+	#[test]
+	fn match_paths_is_independent_of_thread_count() {
+		let matched_with = |threads, query| {
+			let mut tree = Tree::from_paths(create_test_paths());
+			tree.threads = threads;
+			tree.filter(query);
+			tree.paths
+				.iter()
+				.map(|p| (p.borrow().matched, p.borrow().match_text.clone()))
+				.collect::<Vec<_>>()
+		};
+
+		for query in &["c", "./s", "src/b"] {
+			let expected = matched_with(1, query);
+			for threads in 2..=12 {
+				assert_eq!(matched_with(threads, query), expected);
+			}
+		}
+	}
+
+	#[test]
+	fn cancelled_filter_does_not_complete() {
+		let mut tree = Tree::from_paths(create_test_paths());
+		tree.threads = 4;
+		assert!(!tree.filter_until("c", &|| true));
+		assert!(tree.filter_until("c", &|| false));
+		assert_eq!(tree.calc_n_matches(), 8);
+	}
+
 	#[test]
 	fn merging_indices_works() {
 		let created: Vec<Vec<MatchIdx>> = vec![
@@ -691,6 +1366,11 @@ mod test {
 				MatchIdx { start: 1, end: 6 },
 				MatchIdx { start: 33, end: 36 },
 			],
+			vec![
+				MatchIdx { start: 0, end: 4 },
+				MatchIdx { start: 2, end: 3 },
+				MatchIdx { start: 3, end: 6 },
+			],
 		]
 		.into_iter()
 		.map(merge_adjacent_indices)
@@ -703,6 +1383,7 @@ mod test {
 				MatchIdx { start: 1, end: 12 },
 				MatchIdx { start: 33, end: 36 },
 			],
+			vec![MatchIdx { start: 0, end: 6 }],
 		];
 
 		assert_eq!(created, expected);
@@ -710,11 +1391,41 @@ mod test {
 
 	#[test]
 	fn adjacent_matches_are_colored_correctly() {
-		let paths = vec![path::Path::new("path/sha1.js".to_string(), false)];
-		match_paths(&paths, &vec!["s", "ha"]);
+		let paths = paths!["path/sha1.js"];
+		match_paths(&paths, &["s", "ha"], 1, &Highlight::default(), &|| false);
+		assert_eq!(
+			paths[2].borrow().match_text,
+			Some(format!("{}sha{}1.j{}s{}", BLUE, RESET, BLUE, RESET))
+		);
+	}
+
+	#[test]
+	fn snapping_indices_to_graphemes() {
+		let name = "cafe\u{301}s";
+		assert_eq!(
+			snap_to_graphemes(name, match_indices(&["caf", "e"], name)),
+			vec![MatchIdx { start: 0, end: 3 }, MatchIdx { start: 3, end: 6 }]
+		);
+		assert_eq!(
+			snap_to_graphemes(name, match_indices(&["\u{301}"], name)),
+			vec![MatchIdx { start: 3, end: 6 }]
+		);
+	}
+
+	#[test]
+	fn wide_and_combining_matches_are_colored_correctly() {
+		let paths = paths!["日本語.txt"];
+		match_paths(&paths, &["本"], 1, &Highlight::default(), &|| false);
 		assert_eq!(
-			paths[0].borrow().match_text,
-			format!("{}sha{}1.j{}s{}", BLUE, RESET, BLUE, RESET)
+			paths[1].borrow().match_text,
+			Some(format!("日{}本{}語.txt", BLUE, RESET))
+		);
+
+		let paths = paths!["cafe\u{301}s.txt"];
+		match_paths(&paths, &["e"], 1, &Highlight::default(), &|| false);
+		assert_eq!(
+			paths[1].borrow().match_text,
+			Some(format!("caf{}e\u{301}{}s.txt", BLUE, RESET))
 		);
 	}
 }
@@ -4,63 +4,383 @@
 // distributed except according to those terms.
 
 use crate::path;
+use crate::preview::Position;
+use crate::text;
+use crate::theme::Theme;
+use signal_hook::consts::SIGWINCH;
+use signal_hook::iterator::Signals;
 use std::cmp;
 use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
 use termion::cursor::DetectCursorPos;
-use termion::input::TermRead;
+use termion::event::{Event as TermEvent, Key, MouseButton, MouseEvent};
+use termion::input::{MouseTerminal, TermRead};
 use termion::raw::IntoRawMode;
-use termion::{clear, color, cursor, scroll};
+use termion::screen::AlternateScreen;
+use termion::{clear, cursor, scroll};
+use unicode_segmentation::UnicodeSegmentation;
 
-pub fn println_cleared(s: &str) {
-	print!("{}{}\r\n", clear::CurrentLine, s);
+fn chars_to_str(chars: &[char]) -> String {
+	chars.iter().collect::<String>()
 }
 
-fn chars_to_str(chars: &Vec<char>) -> String {
-	chars.iter().collect::<String>()
+/// Indices into `chars` at which graphemes start, followed by its length.
+fn grapheme_bounds(chars: &[char]) -> Vec<usize> {
+	let mut bounds = vec![0];
+	for grapheme in chars_to_str(chars).graphemes(true) {
+		bounds.push(bounds[bounds.len() - 1] + grapheme.chars().count());
+	}
+	bounds
+}
+
+/// The start of the grapheme in `chars` before index `pos`.
+fn prev_grapheme(chars: &[char], pos: usize) -> usize {
+	grapheme_bounds(chars)
+		.into_iter()
+		.rev()
+		.find(|&b| b < pos)
+		.unwrap_or(0)
+}
+
+/// The end of the grapheme in `chars` after index `pos`.
+fn next_grapheme(chars: &[char], pos: usize) -> usize {
+	grapheme_bounds(chars)
+		.into_iter()
+		.find(|&b| b > pos)
+		.unwrap_or(chars.len())
 }
 
-fn print_tree(lines: &[String], pos: u16, display_lines: usize) {
+/// Print `lines` at `positions`, which also limits the number of lines shown.
+/// Cut a line of the tree down to the `width` columns from `skip`, and put
+/// its metadata `cols` after it, at the right of `width + cols_width`.
+fn fit_line(line: &str, cols: &str, skip: usize, width: usize, cols_width: usize) -> String {
+	let mut fitted = text::clip(line, skip, width);
+	if cols_width > 0 {
+		fitted.push_str(&" ".repeat(width.saturating_sub(text::width(&fitted))));
+		let cols = text::clip(cols, 0, cols_width);
+		fitted.push_str(&" ".repeat(cols_width - text::width(&cols)));
+		fitted.push_str(&cols);
+	}
+	fitted
+}
+
+fn print_tree(lines: &[String], pos: u16, positions: &[cursor::Goto], theme: &Theme) {
 	let highlight = format!(
 		"{}{}>{}",
-		color::Bg(color::Rgb(50, 50, 50)),
-		color::Fg(color::Red),
-		color::Fg(color::Reset),
+		theme.bg_current.bg(),
+		theme.pointer.fg(),
+		theme.pointer.fg_reset(),
 	);
+	let normal = format!("{} ", theme.bg.bg());
 
-	for (i, line) in lines.iter().enumerate() {
+	for (i, (line, goto)) in lines.iter().zip(positions).enumerate() {
+		let current = i == (pos as usize);
 		print!(
-			"{}{}{}{}",
+			"{}{}{}{}{}",
+			goto,
 			clear::CurrentLine,
-			if i == (pos as usize) { &highlight } else { " " },
+			if current { &highlight } else { &normal },
 			line,
-			color::Bg(color::Reset),
+			if current {
+				theme.bg_current.bg_reset()
+			} else {
+				theme.bg.bg_reset()
+			},
 		);
-		if i == display_lines - 1 {
-			break;
-		}
-		print!("\r\n"); // NB don't print out the extra CRLF on the last line!
 	}
 }
 
-pub fn print_info_line(text: String) {
-	println_cleared(&format!(
-		"{}{}{}",
-		color::Fg(color::LightGreen),
+pub fn print_info_line(text: String, theme: &Theme) {
+	print!(
+		"{}{}{}{}",
+		clear::CurrentLine,
+		theme.info.fg(),
 		text,
-		color::Fg(color::Reset),
-	));
+		theme.info.fg_reset(),
+	);
+}
+
+/// Something for the main loop to handle.
+#[derive(Debug)]
+pub enum Event {
+	Key(Key),
+	Mouse(Mouse),
+	/// A preview has finished and can be drawn
+	Preview,
+	/// The terminal has been resized
+	Resize,
+}
+
+/// A press of a mouse button at a position on the terminal.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Mouse {
+	pub button: MouseButton,
+	pub x: u16,
+	pub y: u16,
+	/// Whether shift, alt or ctrl was held
+	pub modified: bool,
+}
+
+/// The event to handle for something read from the terminal, if any.
+fn to_event(event: TermEvent) -> Option<Event> {
+	match event {
+		TermEvent::Key(key) => Some(Event::Key(key)),
+		TermEvent::Mouse(MouseEvent::Press(button, x, y)) => Some(Event::Mouse(Mouse {
+			button,
+			x,
+			y,
+			modified: false,
+		})),
+		TermEvent::Mouse(_) => None,
+		TermEvent::Unsupported(bytes) => parse_modified_press(&bytes).map(Event::Mouse),
+	}
+}
+
+/// termion doesn't parse presses with modifiers held, so these are parsed
+/// from their xterm encoding, `ESC [ < Cb ; Cx ; Cy M`.
+fn parse_modified_press(bytes: &[u8]) -> Option<Mouse> {
+	let params = std::str::from_utf8(bytes)
+		.ok()?
+		.strip_prefix("\x1b[<")?
+		.strip_suffix('M')?;
+	let mut params = params.split(';').map(|n| n.parse::<u16>().ok());
+	let (cb, x, y) = (params.next()??, params.next()??, params.next()??);
+	// Shift, alt and ctrl add 4, 8 and 16 to the button, and motion adds 32
+	let modifiers = cb & 0b11100;
+	let button = match cb & !modifiers {
+		0 => MouseButton::Left,
+		1 => MouseButton::Middle,
+		2 => MouseButton::Right,
+		64 => MouseButton::WheelUp,
+		65 => MouseButton::WheelDown,
+		_ => return None,
+	};
+	Some(Mouse {
+		button,
+		x,
+		y,
+		modified: modifiers != 0,
+	})
+}
+
+/// Sends events to be handled alongside keys.
+pub type EventSender = mpsc::Sender<Result<Event, io::Error>>;
+
+/// Reads keys and mouse presses from stdin on a background thread so that
+/// input is never blocked by matching. Keeps count of input which has been
+/// read but not yet handled, which lets long running work give way to newer
+/// input. Other threads can send events through the same queue.
+pub struct KeyReader {
+	tx: EventSender,
+	rx: mpsc::Receiver<Result<Event, io::Error>>,
+	pending: PendingKeys,
+}
+
+/// A count of keys read by a `KeyReader` which have not yet been handled.
+/// This can be shared with other threads.
+#[derive(Clone)]
+pub struct PendingKeys(Arc<AtomicUsize>);
+
+impl PendingKeys {
+	/// Whether there are keys waiting to be handled.
+	pub fn any(&self) -> bool {
+		self.0.load(Ordering::SeqCst) > 0
+	}
+}
+
+impl KeyReader {
+	/// Start reading keys. NB this must be called after `Tui::new`, which reads
+	/// the cursor position from stdin.
+	pub fn spawn() -> Self {
+		let (tx, rx) = mpsc::channel();
+		let pending = PendingKeys(Arc::new(AtomicUsize::new(0)));
+		let counter = Arc::clone(&pending.0);
+		let keys_tx = tx.clone();
+
+		thread::spawn(move || {
+			for event in io::stdin().events() {
+				let event = match event.map(to_event).transpose() {
+					Some(event) => event,
+					None => continue,
+				};
+				counter.fetch_add(1, Ordering::SeqCst);
+				if keys_tx.send(event).is_err() {
+					break;
+				}
+			}
+		});
+
+		KeyReader { tx, rx, pending }
+	}
+
+	/// Get a sender for events from other threads.
+	pub fn sender(&self) -> EventSender {
+		self.tx.clone()
+	}
+
+	/// Get a shareable count of the keys waiting to be handled.
+	pub fn pending(&self) -> PendingKeys {
+		self.pending.clone()
+	}
+}
+
+impl Iterator for KeyReader {
+	type Item = Result<Event, io::Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let event = self.rx.recv().ok()?;
+		if matches!(event, Ok(Event::Key(_)) | Ok(Event::Mouse(_)) | Err(_)) {
+			self.pending.0.fetch_sub(1, Ordering::SeqCst);
+		}
+		Some(event)
+	}
+}
+
+/// Send `Event::Resize` on `events` whenever the terminal is resized.
+pub fn watch_resize(events: EventSender) -> Result<(), io::Error> {
+	let mut signals = Signals::new([SIGWINCH])?;
+	thread::spawn(move || {
+		for _ in signals.forever() {
+			if events.send(Ok(Event::Resize)).is_err() {
+				break;
+			}
+		}
+	});
+	Ok(())
+}
+
+/// The number of lines to draw on for a terminal with `rows` rows. This is
+/// always enough for the input and info lines and one path, even if the
+/// terminal is too small to show them.
+fn display_lines(height: Height, fullscreen: bool, rows: u16) -> usize {
+	// One more line than `display_lines` is drawn on
+	let available = (rows as usize).saturating_sub(1);
+	let lines = if fullscreen {
+		available
+	} else {
+		cmp::min(height.lines(rows as usize), available)
+	};
+	cmp::max(lines, 2)
+}
+
+/// Split the `body` lines below the prompt between the tree and a preview
+/// pane below it, returning the number of lines of each. A line separating
+/// them is left over, unless there is no room for the pane, when it has none.
+fn split_below(body: usize) -> (usize, usize) {
+	match (body / 2).saturating_sub(1) {
+		0 => (body, 0),
+		pane => (body - body / 2, pane),
+	}
+}
+
+/// The preview pane and the part of the preview shown in it.
+struct Pane {
+	position: Position,
+	shown: bool,
+	lines: Vec<String>,
+	offset: usize,
+}
+
+/// How much of the terminal to draw on, when not full screen.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Height {
+	Lines(usize),
+	/// A percentage of the terminal's height
+	Percent(usize),
+}
+
+impl Height {
+	/// The fewest lines which can be drawn on.
+	pub const MIN_LINES: usize = 3;
+
+	/// The number of lines to use on a terminal with `rows` rows.
+	fn lines(self, rows: usize) -> usize {
+		let lines = match self {
+			Height::Lines(n) => n,
+			Height::Percent(p) => rows * p / 100,
+		};
+		cmp::max(lines, Height::MIN_LINES)
+	}
+}
+
+impl Default for Height {
+	fn default() -> Self {
+		Height::Lines(20)
+	}
+}
+
+impl FromStr for Height {
+	type Err = String;
+
+	/// Parse a number of lines, e.g. `20`, or a percentage, e.g. `40%`.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || format!("invalid height: {}", s);
+		match s.strip_suffix('%') {
+			Some(p) => match p.parse() {
+				Ok(p) if p > 0 && p <= 100 => Ok(Height::Percent(p)),
+				_ => Err(invalid()),
+			},
+			None => match s.parse() {
+				Ok(n) if n >= Height::MIN_LINES => Ok(Height::Lines(n)),
+				Ok(_) => Err(format!("height must be >={}", Height::MIN_LINES)),
+				Err(_) => Err(invalid()),
+			},
+		}
+	}
 }
 
-pub fn iter_keys() -> termion::input::Keys<io::Stdin> {
-	io::stdin().keys()
+/// Where the input and info lines are drawn relative to the tree.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PromptPosition {
+	#[default]
+	Top,
+	Bottom,
+}
+
+impl FromStr for PromptPosition {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"top" => Ok(PromptPosition::Top),
+			"bottom" => Ok(PromptPosition::Bottom),
+			_ => Err(format!("unknown prompt position: {}", s)),
+		}
+	}
+}
+
+/// Where and how the interface is drawn.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Layout {
+	pub height: Height,
+	pub fullscreen: bool,
+	/// Draw the tree from the bottom up
+	pub reverse: bool,
+	pub prompt: PromptPosition,
+	/// Where to show the preview pane, if paths are previewed
+	pub preview: Option<Position>,
 }
 
 type RawStdout = termion::raw::RawTerminal<io::Stdout>;
 
+/// Number of columns the tree scrolls sideways by.
+const SCROLL_COLUMNS: usize = 8;
+/// Longest time between two clicks on a line for them to be a double click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
 pub struct Tui {
 	stdout: RawStdout,
+	/// The alternate screen, which is drawn on when full screen
+	screen: Option<AlternateScreen<io::Stdout>>,
+	/// Reports the mouse as input while it is held
+	mouse: Option<MouseTerminal<io::Stdout>>,
 	start_pos: (u16, u16),
 	prompt: String,
+	layout: Layout,
 	display_lines: usize,
 	offset: usize, // TODO: Keep these in a TuiState?
 	chars: Vec<char>,
@@ -69,33 +389,56 @@ pub struct Tui {
 	curs_pos: u16,
 	line_pos: u16,
 	current_lines: usize,
+	/// Number of columns the tree is scrolled to the right
+	h_offset: usize,
+	/// Width of the widest line on screen
+	max_width: usize,
+	/// Width of the widest metadata columns on screen, drawn right of the tree
+	cols_width: usize,
+	/// Number of columns each line on screen is scrolled by
+	skips: Vec<usize>,
+	/// When and on which line the mouse was last clicked
+	last_click: Option<(Instant, usize)>,
+	theme: Theme,
+	preview: Option<Pane>,
 }
 
 impl Tui {
+	/// Start drawing below the cursor on the layout's height of the terminal,
+	/// or on all of an alternate screen if it is full screen.
 	pub fn new(
 		prompt: String,
-		mut display_lines: usize,
+		layout: Layout,
 		current_lines: usize,
+		theme: Theme,
 	) -> Result<Self, io::Error> {
 		let mut stdout = io::stdout().into_raw_mode()?;
-		let mut start_pos = stdout.cursor_pos()?;
-
-		// Scroll up to allow min screen space at bottom of screen
 		let size = termion::terminal_size()?;
-		display_lines = cmp::min(display_lines, size.1 as usize);
 		debug!("Terminal size: {:?}", size);
-		debug!("Starting pos: {:?}", start_pos);
-		let min_line = size.1 - display_lines as u16;
-		if min_line < start_pos.1 {
-			let diff = start_pos.1 - min_line;
-			debug!("Scrolling up {} lines", diff);
-			print!("{}", scroll::Up(diff));
-			start_pos.1 = min_line;
-		}
+		let display_lines = display_lines(layout.height, layout.fullscreen, size.1);
+
+		let (screen, start_pos) = if layout.fullscreen {
+			(Some(AlternateScreen::from(io::stdout())), (1, 1))
+		} else {
+			let mut start_pos = stdout.cursor_pos()?;
+			debug!("Starting pos: {:?}", start_pos);
+
+			// Scroll up to allow min screen space at bottom of screen
+			let min_line = size.1.saturating_sub(display_lines as u16).max(1);
+			if min_line < start_pos.1 {
+				let diff = start_pos.1 - min_line;
+				debug!("Scrolling up {} lines", diff);
+				print!("{}", scroll::Up(diff));
+				start_pos.1 = min_line;
+			}
+			(None, start_pos)
+		};
 
 		Ok(Tui {
-			stdout: stdout,
-			start_pos: start_pos,
+			stdout,
+			screen,
+			mouse: None,
+			start_pos,
 			curs_pos: 0,
 			line_pos: 0,
 			offset: 0,
@@ -103,8 +446,21 @@ impl Tui {
 			stash: Vec::new(),
 			chars_changed: false,
 			prompt,
+			layout,
 			display_lines,
 			current_lines,
+			h_offset: 0,
+			max_width: 0,
+			cols_width: 0,
+			skips: Vec::new(),
+			last_click: None,
+			theme,
+			preview: layout.preview.map(|position| Pane {
+				position,
+				shown: true,
+				lines: Vec::new(),
+				offset: 0,
+			}),
 		})
 	}
 
@@ -121,17 +477,172 @@ impl Tui {
 		)
 	}
 
+	fn goto_row(&self, row: u16) -> cursor::Goto {
+		cursor::Goto(self.start_pos.0, row)
+	}
+
+	fn input_row(&self) -> u16 {
+		match self.layout.prompt {
+			PromptPosition::Top => self.start_pos.1,
+			PromptPosition::Bottom => self.start_pos.1 + self.display_lines as u16,
+		}
+	}
+
+	fn info_row(&self) -> u16 {
+		match self.layout.prompt {
+			PromptPosition::Top => self.start_pos.1 + 1,
+			PromptPosition::Bottom => self.start_pos.1 + self.display_lines as u16 - 1,
+		}
+	}
+
+	/// The first row of the tree and preview.
+	fn body_row(&self) -> u16 {
+		match self.layout.prompt {
+			PromptPosition::Top => self.start_pos.1 + 2,
+			PromptPosition::Bottom => self.start_pos.1,
+		}
+	}
+
 	fn print_input_line(&self) {
-		println_cleared(&format!("{}{}", self.prompt, &chars_to_str(&self.chars)));
+		print!(
+			"{}{}{}{}",
+			self.goto_row(self.input_row()),
+			clear::CurrentLine,
+			self.prompt,
+			&chars_to_str(&self.chars)
+		);
 	}
 
-	fn print_body(&self, lines: Vec<String>) {
-		print!("{}", clear::AfterCursor);
-		print_tree(&lines[self.offset..], self.line_pos, self.display_lines - 1);
+	fn print_body(&mut self, lines: Vec<String>, columns: Vec<String>) {
+		let width = self.lines_width();
+		let cols_width = self.tree_width() - width;
+		let hl = self.theme.highlight();
+		let end = cmp::min(lines.len(), self.offset + self.tree_lines());
+		let lines = &lines[self.offset..end];
+		let columns = columns.get(self.offset..end).unwrap_or(&[]);
+		// Scroll further along lines whose match would be cut off
+		self.skips = lines
+			.iter()
+			.map(|line| {
+				text::last_styled(line, &hl.start).map_or(self.h_offset, |(_, end)| {
+					cmp::max(self.h_offset, (end + 1).saturating_sub(width))
+				})
+			})
+			.collect();
+		let lines: Vec<String> = lines
+			.iter()
+			.zip(&self.skips)
+			.enumerate()
+			.map(|(i, (line, &skip))| {
+				let cols = columns.get(i).map_or("", String::as_str);
+				fit_line(line, cols, skip, width, cols_width)
+			})
+			.collect();
+
+		let top = self.body_row();
+		let n = self.tree_lines() as u16;
+		let positions: Vec<_> = (0..n)
+			.map(|i| {
+				self.goto_row(if self.layout.reverse {
+					top + n - 1 - i
+				} else {
+					top + i
+				})
+			})
+			.collect();
+		print_tree(&lines, self.line_pos, &positions, &self.theme);
+		self.print_preview();
+	}
+
+	/// Number of lines the tree is drawn on, which is fewer when the preview
+	/// is shown below it.
+	fn tree_lines(&self) -> usize {
+		let body = self.display_lines - 1;
+		match &self.preview {
+			Some(pane) if pane.shown && pane.position == Position::Down => split_below(body).0,
+			_ => body,
+		}
+	}
+
+	/// Number of columns the lines of the tree are drawn on, left of any
+	/// metadata columns, which keep at least one column for the tree.
+	fn lines_width(&self) -> usize {
+		cmp::max(self.tree_width().saturating_sub(self.cols_width), 1)
+	}
+
+	/// Number of columns the tree can be drawn on, after the pointer.
+	fn tree_width(&self) -> usize {
+		let width = termion::terminal_size().map_or(80, |size| size.0) as usize;
+		let width = match &self.preview {
+			Some(pane) if pane.shown && pane.position == Position::Right => width / 2,
+			_ => width,
+		};
+		cmp::max(width.saturating_sub(1), 1)
+	}
+
+	/// Draw the preview pane over the right half of the tree, or below it.
+	fn print_preview(&self) {
+		let pane = match &self.preview {
+			Some(pane) if pane.shown => pane,
+			_ => return,
+		};
+		let width = termion::terminal_size().map_or(80, |size| size.0);
+		let top = self.body_row();
+		let tree_lines = self.tree_lines() as u16;
+
+		let (col, row, pane_width, height) = match pane.position {
+			Position::Right => {
+				let border = width / 2 + 1;
+				for i in 0..tree_lines {
+					print!(
+						"{}{}{}│{}",
+						cursor::Goto(border, top + i),
+						clear::UntilNewline,
+						self.theme.info.fg(),
+						self.theme.info.fg_reset(),
+					);
+				}
+				(
+					border + 2,
+					top,
+					width.saturating_sub(border + 1),
+					tree_lines,
+				)
+			}
+			Position::Down => {
+				let height = split_below(self.display_lines - 1).1 as u16;
+				if height == 0 {
+					// Too few lines for the pane, which is left out
+					return;
+				}
+				let separator = top + tree_lines;
+				print!(
+					"{}{}{}{}{}",
+					cursor::Goto(1, separator),
+					clear::CurrentLine,
+					self.theme.info.fg(),
+					"─".repeat(width as usize),
+					self.theme.info.fg_reset(),
+				);
+				(1, separator + 1, width, height)
+			}
+		};
+
+		for i in 0..height {
+			print!("{}{}", cursor::Goto(col, row + i), clear::UntilNewline);
+			if let Some(line) = pane.lines.get(pane.offset + i as usize) {
+				print!("{}", text::clip(line, 0, pane_width as usize));
+			}
+		}
 	}
 
 	fn return_cursor(&self) {
-		print!("{}", cursor::Goto(self.curs_pos + 3, self.start_pos.1));
+		let before = chars_to_str(&self.chars[..self.curs_pos as usize]);
+		let col = text::width(&self.prompt) + text::width(&before);
+		print!(
+			"{}",
+			cursor::Goto(self.start_pos.0 + col as u16, self.input_row())
+		);
 	}
 
 	pub fn flush(&mut self) -> Result<(), io::Error> {
@@ -160,7 +671,7 @@ impl Tui {
 		let x = self.line_pos as usize;
 		if x + self.offset == self.current_lines - 1 {
 			// Do nout
-		} else if x == self.display_lines - 2 {
+		} else if x == self.tree_lines() - 1 {
 			self.offset += 1;
 		} else {
 			self.line_pos += 1;
@@ -194,18 +705,95 @@ impl Tui {
 		}
 	}
 
-	pub fn move_left(&mut self) {
-		if self.curs_pos > 0 {
-			self.curs_pos -= 1;
+	/// Scroll the tree by `lines`, down if positive, keeping the cursor on the
+	/// same path unless it would leave the screen.
+	pub fn scroll(&mut self, lines: isize) {
+		let index = self.index();
+		let last = cmp::max(
+			self.offset,
+			self.current_lines.saturating_sub(self.tree_lines()),
+		);
+		self.offset = (self.offset as isize + lines).max(0).min(last as isize) as usize;
+		let bottom = cmp::min(self.current_lines, self.offset + self.tree_lines());
+		let index = cmp::max(self.offset, cmp::min(index, bottom.saturating_sub(1)));
+		self.line_pos = (index - self.offset) as u16;
+	}
+
+	/// The index of the tree line drawn at (`x`, `y`) on the terminal, along
+	/// with the column of that line which is drawn there.
+	pub fn line_at(&self, x: u16, y: u16) -> Option<(usize, usize)> {
+		let top = self.body_row();
+		let n = self.tree_lines() as u16;
+		if y < top || y >= top + n {
+			return None;
 		}
+		let row = (y - top) as usize;
+		let i = if self.layout.reverse {
+			n as usize - 1 - row
+		} else {
+			row
+		};
+		let skip = *self.skips.get(i)?;
+		// Lines are drawn after the pointer
+		let col = x.saturating_sub(self.start_pos.0 + 1) as usize;
+		if col >= self.lines_width() {
+			return None;
+		}
+		Some((self.offset + i, skip + col))
 	}
 
-	pub fn move_right(&mut self) {
-		if (self.curs_pos as usize) < self.chars.len() {
-			self.curs_pos += 1;
+	/// Whether (`x`, `y`) on the terminal is within the preview pane.
+	pub fn over_preview(&self, x: u16, y: u16) -> bool {
+		let pane = match &self.preview {
+			Some(pane) if pane.shown => pane,
+			_ => return false,
+		};
+		let top = self.body_row();
+		if y < top || y >= top + self.display_lines as u16 - 1 {
+			return false;
+		}
+		match pane.position {
+			Position::Right => {
+				let width = termion::terminal_size().map_or(80, |size| size.0);
+				x > width / 2
+			}
+			Position::Down => y > top + self.tree_lines() as u16,
 		}
 	}
 
+	/// Move the cursor to the `index`th line, which has been clicked. Returns
+	/// whether this was the second click on it in quick succession.
+	pub fn click(&mut self, index: usize) -> bool {
+		self.line_pos = (index - self.offset) as u16;
+		let now = Instant::now();
+		let double = matches!(
+			self.last_click,
+			Some((at, i)) if i == index && now.duration_since(at) < DOUBLE_CLICK
+		);
+		self.last_click = if double { None } else { Some((now, index)) };
+		double
+	}
+
+	/// Scroll the tree left, towards the start of its lines.
+	pub fn scroll_left(&mut self) {
+		self.h_offset = self.h_offset.saturating_sub(SCROLL_COLUMNS);
+	}
+
+	/// Scroll the tree right, unless the ends of all lines are already shown.
+	pub fn scroll_right(&mut self) {
+		if self.h_offset + self.lines_width() < self.max_width {
+			self.h_offset += SCROLL_COLUMNS;
+		}
+	}
+
+	pub fn move_left(&mut self) {
+		self.curs_pos = prev_grapheme(&self.chars, self.curs_pos as usize) as u16;
+	}
+
+	pub fn move_right(&mut self) {
+		self.curs_pos = next_grapheme(&self.chars, self.curs_pos as usize) as u16;
+	}
+
 	pub fn word_stash(&mut self) {
 		if self.curs_pos < 1 {
 			return;
@@ -274,31 +862,106 @@ impl Tui {
 
 	pub fn backspace(&mut self) {
 		if self.curs_pos > 0 {
-			self.chars.remove((self.curs_pos - 1) as usize);
-			self.curs_pos -= 1;
+			let start = prev_grapheme(&self.chars, self.curs_pos as usize);
+			self.chars.drain(start..self.curs_pos as usize);
+			self.curs_pos = start as u16;
 			self.chars_changed = true;
 		}
 	}
 
 	pub fn delete(&mut self) {
 		if (self.curs_pos as usize) < self.chars.len() {
-			self.chars.remove(self.curs_pos as usize);
+			let end = next_grapheme(&self.chars, self.curs_pos as usize);
+			self.chars.drain(self.curs_pos as usize..end);
 			self.chars_changed = true;
 		}
 	}
 
-	pub fn print_paths(&mut self, paths: &Vec<path::RcPath>) {
-		self.goto_start();
-		print!("{}", clear::AfterCursor);
-		let _ = paths
-			.iter()
-			.map(|p| {
-				let p = p.borrow();
-				if p.selected {
-					print!("{} ", &p.joined);
-				}
-			})
-			.collect::<()>();
+	pub fn print_paths(&mut self, paths: &[path::ArcPath]) {
+		self.clear();
+		for p in paths {
+			if p.borrow().selected {
+				print!("{} ", p.joined());
+			}
+		}
+	}
+
+	/// Report clicks and the mouse wheel as input, until the interface is
+	/// cleared or dropped.
+	pub fn enable_mouse(&mut self) {
+		self.mouse = Some(MouseTerminal::from(io::stdout()));
+	}
+
+	/// Whether the preview pane is shown.
+	pub fn preview_shown(&self) -> bool {
+		self.preview.as_ref().is_some_and(|pane| pane.shown)
+	}
+
+	/// Show or hide the preview pane, keeping the cursor within the tree.
+	pub fn toggle_preview(&mut self) {
+		if let Some(pane) = &mut self.preview {
+			pane.shown = !pane.shown;
+		}
+		self.clamp_cursor();
+	}
+
+	/// Keep the cursor on the same path when the tree is drawn on fewer lines.
+	fn clamp_cursor(&mut self) {
+		let last = self.tree_lines() as u16 - 1;
+		if self.line_pos > last {
+			self.offset += (self.line_pos - last) as usize;
+			self.line_pos = last;
+		}
+	}
+
+	/// Fit the interface to the terminal after it has been resized. It must
+	/// then be rendered again.
+	pub fn resize(&mut self) -> Result<(), io::Error> {
+		let size = termion::terminal_size()?;
+		debug!("Resized to: {:?}", size);
+		self.display_lines = display_lines(self.layout.height, self.screen.is_some(), size.1);
+		if self.screen.is_none() {
+			// Lines below the bottom of the terminal can't be drawn on
+			let min_line = size.1.saturating_sub(self.display_lines as u16).max(1);
+			self.start_pos.1 = cmp::min(self.start_pos.1, min_line);
+		}
+		self.clamp_cursor();
+		Ok(())
+	}
+
+	/// Replace the previewed lines, scrolling back to the top.
+	pub fn set_preview(&mut self, lines: Vec<String>) {
+		if let Some(pane) = &mut self.preview {
+			pane.lines = lines;
+			pane.offset = 0;
+		}
+	}
+
+	/// Half the number of lines in the preview pane, for scrolling by.
+	pub fn preview_half(&self) -> isize {
+		let lines = match &self.preview {
+			Some(pane) if pane.position == Position::Down => split_below(self.display_lines - 1).1,
+			_ => self.tree_lines(),
+		};
+		(lines / 2).max(1) as isize
+	}
+
+	/// Scroll the preview by `lines`, down if positive.
+	pub fn scroll_preview(&mut self, lines: isize) {
+		if let Some(pane) = &mut self.preview {
+			let last = pane.lines.len().saturating_sub(1) as isize;
+			pane.offset = (pane.offset as isize + lines).max(0).min(last) as usize;
+		}
+	}
+
+	/// Clear everything drawn by the interface. When full screen, this returns
+	/// to the main screen, leaving the cursor where it was before starting.
+	pub fn clear(&mut self) {
+		self.mouse = None;
+		if self.screen.take().is_none() {
+			self.goto_start();
+			print!("{}", clear::AfterCursor);
+		}
 	}
 
 	fn adjust_offset(&mut self, new_len: usize) {
@@ -308,18 +971,57 @@ impl Tui {
 		}
 	}
 
-	pub fn render(&mut self, info_line: String, path_lines: Vec<String>) -> Result<(), io::Error> {
-		if self.chars_changed && self.index() >= path_lines.len() {
+	/// Redraw only the input line. Used to keep the prompt responsive while
+	/// the body is out of date.
+	pub fn render_input(&mut self) -> Result<(), io::Error> {
+		self.print_input_line();
+		self.return_cursor();
+		self.flush()
+	}
+
+	/// Redraw only the preview pane, e.g. once a preview has finished.
+	pub fn render_preview(&mut self) -> Result<(), io::Error> {
+		self.print_preview();
+		self.return_cursor();
+		self.flush()
+	}
+
+	/// Draw the prompt, `info_line` and the tree's `path_lines`, with the
+	/// metadata `columns` of each line right-aligned beside them.
+	pub fn render(
+		&mut self,
+		info_line: String,
+		path_lines: Vec<String>,
+		columns: Vec<String>,
+	) -> Result<(), io::Error> {
+		// The cursor's line may have gone, e.g. when directories are collapsed
+		if self.index() >= path_lines.len() {
 			self.adjust_offset(path_lines.len());
 			let x = cmp::max(1, path_lines.len()) - 1;
 			self.line_pos = cmp::min(self.line_pos, x as u16);
 		}
 
 		self.current_lines = path_lines.len();
+		self.max_width = path_lines[self.offset..]
+			.iter()
+			.take(self.tree_lines())
+			.map(|line| text::width(line))
+			.max()
+			.unwrap_or(0);
+		self.cols_width = columns
+			.iter()
+			.skip(self.offset)
+			.take(self.tree_lines())
+			.map(|col| text::width(col))
+			.max()
+			.unwrap_or(0);
 		self.goto_start();
+		print!("{}", clear::AfterCursor);
 		self.print_input_line();
-		print_info_line(info_line);
-		self.print_body(path_lines);
+		print!("{}", self.goto_row(self.info_row()));
+		let width = termion::terminal_size().map_or(80, |size| size.0);
+		print_info_line(text::clip(&info_line, 0, width as usize), &self.theme);
+		self.print_body(path_lines, columns);
 		self.return_cursor();
 		self.flush()?;
 		self.chars_changed = false;
@@ -347,6 +1049,111 @@ mod test {
 
 	#[test]
 	fn test_chars_to_string() {
-		assert_eq!(chars_to_str(&vec!['a', 'b', 'c']), "abc");
+		assert_eq!(chars_to_str(&['a', 'b', 'c']), "abc");
+	}
+
+	#[test]
+	fn moving_over_graphemes() {
+		let chars: Vec<char> = "ae\u{301}日".chars().collect();
+		assert_eq!(grapheme_bounds(&chars), [0, 1, 3, 4]);
+		assert_eq!(next_grapheme(&chars, 1), 3);
+		assert_eq!(next_grapheme(&chars, 4), 4);
+		assert_eq!(prev_grapheme(&chars, 3), 1);
+		assert_eq!(prev_grapheme(&chars, 2), 1);
+		assert_eq!(prev_grapheme(&chars, 0), 0);
+	}
+
+	#[test]
+	fn parsing_modified_presses() {
+		let press = |button, modified| {
+			Some(Mouse {
+				button,
+				x: 12,
+				y: 3,
+				modified,
+			})
+		};
+		assert_eq!(
+			parse_modified_press(b"\x1b[<16;12;3M"),
+			press(MouseButton::Left, true)
+		);
+		assert_eq!(
+			parse_modified_press(b"\x1b[<4;12;3M"),
+			press(MouseButton::Left, true)
+		);
+		assert_eq!(
+			parse_modified_press(b"\x1b[<81;12;3M"),
+			press(MouseButton::WheelDown, true)
+		);
+		assert_eq!(
+			parse_modified_press(b"\x1b[<0;12;3M"),
+			press(MouseButton::Left, false)
+		);
+		// Releases and motion aren't presses
+		assert_eq!(parse_modified_press(b"\x1b[<16;12;3m"), None);
+		assert_eq!(parse_modified_press(b"\x1b[<48;12;3M"), None);
+		assert_eq!(parse_modified_press(b"\x1b[<16;12M"), None);
+		assert_eq!(parse_modified_press(b"\x1bOP"), None);
+	}
+
+	#[test]
+	fn columns_stay_right_of_long_lines() {
+		// The columns keep their place whether or not a line is cut short
+		assert_eq!(fit_line(" short", "  1.0K", 0, 10, 6), " short      1.0K");
+		assert_eq!(
+			fit_line(" a/very/long/path", "  1.0K", 0, 10, 6),
+			" a/very/l…  1.0K"
+		);
+		assert_eq!(
+			fit_line(" a/very/long/path", "  20", 4, 10, 6),
+			"…ry/long/…    20"
+		);
+		assert_eq!(fit_line(" a", "", 0, 10, 0), " a");
+	}
+
+	#[test]
+	fn parsing_heights() {
+		assert_eq!("20".parse(), Ok(Height::Lines(20)));
+		assert_eq!("40%".parse(), Ok(Height::Percent(40)));
+		assert!("2".parse::<Height>().is_err());
+		assert!("0%".parse::<Height>().is_err());
+		assert!("101%".parse::<Height>().is_err());
+		assert!("x%".parse::<Height>().is_err());
+	}
+
+	#[test]
+	fn display_lines_fit_the_terminal() {
+		assert_eq!(display_lines(Height::Lines(20), false, 50), 20);
+		assert_eq!(display_lines(Height::Lines(20), false, 10), 9);
+		assert_eq!(display_lines(Height::Percent(50), false, 40), 20);
+		assert_eq!(display_lines(Height::Lines(20), true, 50), 49);
+		// Too small to show anything, but still enough lines to draw on
+		assert_eq!(display_lines(Height::Lines(20), false, 2), 2);
+		assert_eq!(display_lines(Height::Lines(20), true, 0), 2);
+	}
+
+	#[test]
+	fn preview_below_fits_the_body() {
+		assert_eq!(split_below(20), (10, 9));
+		assert_eq!(split_below(5), (3, 1));
+		// No room for the pane and its separator
+		assert_eq!(split_below(3), (3, 0));
+		assert_eq!(split_below(1), (1, 0));
+		let body = display_lines(Height::Lines(20), true, 0) - 1;
+		assert_eq!(split_below(body), (1, 0));
+	}
+
+	#[test]
+	fn parsing_prompt_positions() {
+		assert_eq!("top".parse(), Ok(PromptPosition::Top));
+		assert_eq!("bottom".parse(), Ok(PromptPosition::Bottom));
+		assert!("left".parse::<PromptPosition>().is_err());
+	}
+
+	#[test]
+	fn heights_in_lines() {
+		assert_eq!(Height::Lines(20).lines(50), 20);
+		assert_eq!(Height::Percent(40).lines(50), 20);
+		assert_eq!(Height::Percent(1).lines(50), Height::MIN_LINES);
 	}
 }